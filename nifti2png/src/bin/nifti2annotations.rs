@@ -0,0 +1,84 @@
+use std::{io::BufRead, path::PathBuf};
+
+use nifti2png::{
+    export_annotations, AnnotationFormat, AnnotationOptions, Categories, ConvertOptions, OnError,
+};
+
+#[path = "../log_args.rs"]
+mod log_args;
+
+/// Reads a line as raw bytes, since the paths don't have to be UTF-8
+fn read_path_line() -> PathBuf {
+    let mut line = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_until(b'\n', &mut line)
+        .unwrap();
+    while line.last().is_some_and(|byte| b"\r\n".contains(byte)) {
+        line.pop();
+    }
+    #[cfg(unix)]
+    let path = <std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(line);
+    #[cfg(not(unix))]
+    let path = String::from_utf8(line).expect("Invalid input");
+    PathBuf::from(path)
+}
+
+fn main() {
+    log_args::init_logging();
+
+    println!("Enter a path to the directory with the NIFTI files passed to `nifti2png`:");
+    let mut convert_options = ConvertOptions::builder(read_path_line());
+
+    println!("Enter the `png_stub` passed to `nifti2png`:");
+    let png_stub = read_path_line();
+    if !png_stub.as_os_str().is_empty() {
+        convert_options = convert_options.png_stub(png_stub);
+    }
+
+    println!("Enter `continue` to skip the files that fail to annotate (empty to stop at the first error):");
+    let mut on_error = String::new();
+    std::io::stdin().read_line(&mut on_error).unwrap();
+    convert_options = convert_options.on_error(match on_error.trim_end() {
+        "" => OnError::Stop,
+        "continue" => OnError::Continue,
+        _ => panic!("Invalid input"),
+    });
+
+    println!("Enter a path to a directory with the label volumes:");
+    let labels = read_path_line();
+
+    println!("Enter a path to the JSON file with the categories, e.g. {{\"1\": \"nodule\"}}:");
+    let categories = Categories::load(read_path_line()).unwrap();
+    let mut options = AnnotationOptions::builder(labels, categories);
+
+    println!("Enter the suffix of the names of the label volumes, e.g. `_mask` (empty for the same names as the images):");
+    let mut label_suffix = String::new();
+    std::io::stdin().read_line(&mut label_suffix).unwrap();
+    options = options.label_suffix(label_suffix.trim_end());
+
+    println!("Enter the format: `coco`, `yolo` or `yolo-seg` (empty for coco):");
+    let mut format = String::new();
+    std::io::stdin().read_line(&mut format).unwrap();
+    options = options.format(match format.trim_end() {
+        "" | "coco" => AnnotationFormat::Coco,
+        "yolo" => AnnotationFormat::Yolo,
+        "yolo-seg" => AnnotationFormat::YoloSegments,
+        _ => panic!("Invalid input"),
+    });
+
+    println!("Enter the smallest number of pixels of an annotated component (empty for 1):");
+    let mut min_area = String::new();
+    std::io::stdin().read_line(&mut min_area).unwrap();
+    if !min_area.trim_end().is_empty() {
+        options = options.min_area(min_area.trim_end().parse().expect("Invalid input"));
+    }
+
+    let convert_options = convert_options.build().unwrap();
+    let options = options.build().unwrap();
+    let report = export_annotations(&convert_options, &options).unwrap();
+    println!("{report}");
+    if !report.is_success() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,106 @@
+use std::{io::BufRead, path::PathBuf};
+
+use nifti2png::{export_dataset, DatasetOptions, OnError, SplitRatios};
+
+#[path = "../log_args.rs"]
+mod log_args;
+
+/// Reads a line as raw bytes, since the paths don't have to be UTF-8
+fn read_path_line() -> PathBuf {
+    let mut line = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_until(b'\n', &mut line)
+        .unwrap();
+    while line.last().is_some_and(|byte| b"\r\n".contains(byte)) {
+        line.pop();
+    }
+    #[cfg(unix)]
+    let path = <std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(line);
+    #[cfg(not(unix))]
+    let path = String::from_utf8(line).expect("Invalid input");
+    PathBuf::from(path)
+}
+
+fn main() {
+    log_args::init_logging();
+
+    println!("Enter a path to a directory with the image volumes:");
+    let images = read_path_line();
+
+    println!("Enter a path to a directory with the label volumes (empty for the directory of the images):");
+    let labels = match read_path_line() {
+        labels if labels.as_os_str().is_empty() => images.clone(),
+        labels => labels,
+    };
+
+    println!("Enter a path to the output directory:");
+    let output = read_path_line();
+    let mut options = DatasetOptions::builder(images, labels, output);
+
+    println!("Enter the suffix of the names of the label volumes, e.g. `_mask` (empty for the same names as the images):");
+    let mut label_suffix = String::new();
+    std::io::stdin().read_line(&mut label_suffix).unwrap();
+    options = options.label_suffix(label_suffix.trim_end());
+
+    println!("Enter the `minmax`:");
+    let mut minmax = String::new();
+    std::io::stdin().read_line(&mut minmax).unwrap();
+    match minmax
+        .split_whitespace()
+        .map(|s| s.parse::<u64>())
+        .collect::<Vec<_>>()[..]
+    {
+        [] => (),
+        [Ok(min), Ok(max)] => options = options.minmax(min, max),
+        _ => panic!("Invalid input"),
+    };
+
+    println!(
+        "Enter `skip` to leave out the slices with empty masks (empty to export every slice):"
+    );
+    let mut skip_empty_masks = String::new();
+    std::io::stdin().read_line(&mut skip_empty_masks).unwrap();
+    options = options.skip_empty_masks(match skip_empty_masks.trim_end() {
+        "" => false,
+        "skip" => true,
+        _ => panic!("Invalid input"),
+    });
+
+    println!("Enter the train, validation and test ratios, e.g. `0.7 0.15 0.15` (empty for 0.8 0.1 0.1):");
+    let mut split = String::new();
+    std::io::stdin().read_line(&mut split).unwrap();
+    match split
+        .split_whitespace()
+        .map(|s| s.parse::<f64>())
+        .collect::<Vec<_>>()[..]
+    {
+        [] => (),
+        [Ok(train), Ok(val), Ok(test)] => options = options.split(SplitRatios { train, val, test }),
+        _ => panic!("Invalid input"),
+    };
+
+    println!("Enter the seed of the split (empty for 0):");
+    let mut seed = String::new();
+    std::io::stdin().read_line(&mut seed).unwrap();
+    options = options.seed(match seed.trim_end() {
+        "" => 0,
+        seed => seed.parse().expect("Invalid input"),
+    });
+
+    println!("Enter `continue` to skip the volumes that fail to export (empty to stop at the first error):");
+    let mut on_error = String::new();
+    std::io::stdin().read_line(&mut on_error).unwrap();
+    options = options.on_error(match on_error.trim_end() {
+        "" => OnError::Stop,
+        "continue" => OnError::Continue,
+        _ => panic!("Invalid input"),
+    });
+
+    let options = options.build().unwrap();
+    let report = export_dataset(&options).unwrap();
+    println!("{report}");
+    if !report.is_success() {
+        std::process::exit(1);
+    }
+}
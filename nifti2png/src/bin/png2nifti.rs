@@ -0,0 +1,38 @@
+use std::{io::BufRead, path::PathBuf};
+
+use nifti2png::png2nifti;
+
+#[path = "../log_args.rs"]
+mod log_args;
+
+/// Reads a line as raw bytes, since the paths don't have to be UTF-8
+fn read_path_line() -> PathBuf {
+    let mut line = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_until(b'\n', &mut line)
+        .unwrap();
+    while line.last().is_some_and(|byte| b"\r\n".contains(byte)) {
+        line.pop();
+    }
+    #[cfg(unix)]
+    let path = <std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(line);
+    #[cfg(not(unix))]
+    let path = String::from_utf8(line).expect("Invalid input");
+    PathBuf::from(path)
+}
+
+fn main() {
+    log_args::init_logging();
+
+    println!("Enter a path to a directory with `{{z:04}}.png` slices produced by `nifti2png`:");
+    let png_dir = read_path_line();
+
+    println!("Enter a path to the reference NIFTI file:");
+    let reference = read_path_line();
+
+    println!("Enter a path to the output NIFTI file, e.g. mask.nii.gz:");
+    let output = read_path_line();
+
+    png2nifti(png_dir, reference, output).unwrap();
+}
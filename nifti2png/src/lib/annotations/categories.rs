@@ -0,0 +1,62 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde_json::Value;
+
+use crate::error_ty::ErrorTy::{self, *};
+
+/// Names of the labels of the label volumes, e.g. `1` is `nodule`.
+/// The labels without a name are not annotated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Categories(BTreeMap<u32, String>);
+
+impl Categories {
+    pub fn new(categories: impl IntoIterator<Item = (u32, impl Into<String>)>) -> Self {
+        Self(
+            categories
+                .into_iter()
+                .map(|(label, name)| (label, name.into()))
+                .collect(),
+        )
+    }
+
+    /// Reads the categories from a JSON object mapping the labels to the names,
+    /// e.g. `{"1": "nodule", "2": "vessel"}`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ErrorTy> {
+        let path = path.as_ref();
+        let read_failed = |e: std::io::Error| CategoriesReadFailed(e, path.display().to_string());
+        let bytes = std::fs::read(path).map_err(read_failed)?;
+        let Value::Object(object) =
+            serde_json::from_slice(&bytes).map_err(|e| read_failed(e.into()))?
+        else {
+            return Err(read_failed(std::io::Error::other("not a JSON object")));
+        };
+        let mut categories = BTreeMap::new();
+        for (label, name) in object {
+            let (Ok(label), Value::String(name)) = (label.parse::<u32>(), name) else {
+                return Err(read_failed(std::io::Error::other(format!(
+                    "`{label}` is not a label mapped to a name"
+                ))));
+            };
+            categories.insert(label, name);
+        }
+        Ok(Self(categories))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn name(&self, label: u32) -> Option<&str> {
+        self.0.get(&label).map(String::as_str)
+    }
+
+    /// Zero-based index of the category in the order of the labels, i.e. the YOLO class
+    pub(crate) fn index(&self, label: u32) -> Option<usize> {
+        self.0.keys().position(|l| *l == label)
+    }
+
+    /// Labels and names in the order of the labels
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.0.iter().map(|(label, name)| (*label, name.as_str()))
+    }
+}
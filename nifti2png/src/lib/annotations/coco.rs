@@ -0,0 +1,131 @@
+//! [COCO](https://cocodataset.org/#format-data) object detection format
+
+use serde::Serialize;
+
+use super::{components::Component, Categories};
+
+#[derive(Serialize)]
+struct Image {
+    id: u64,
+    file_name: String,
+    width: usize,
+    height: usize,
+}
+
+#[derive(Serialize)]
+struct Annotation {
+    id: u64,
+    image_id: u64,
+    category_id: u32,
+    // [x, y, width, height]
+    bbox: [usize; 4],
+    area: usize,
+    // A single polygon of flattened [x, y] corners
+    segmentation: Vec<Vec<usize>>,
+    iscrowd: u8,
+}
+
+#[derive(Serialize)]
+struct Category {
+    id: u32,
+    name: String,
+}
+
+/// Annotations of all slices, written at once
+#[derive(Serialize)]
+pub(crate) struct CocoDataset {
+    images: Vec<Image>,
+    annotations: Vec<Annotation>,
+    categories: Vec<Category>,
+}
+
+impl CocoDataset {
+    pub(crate) fn new(categories: &Categories) -> Self {
+        Self {
+            images: Vec::new(),
+            annotations: Vec::new(),
+            categories: categories
+                .iter()
+                .map(|(id, name)| Category {
+                    id,
+                    name: name.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the PNG at `file_name` relative to `png_stub` with the components of its labels.
+    /// The IDs start at 1.
+    pub(crate) fn add_image(
+        &mut self,
+        file_name: String,
+        [width, height]: [usize; 2],
+        components: &[Component],
+    ) {
+        let image_id = self.images.len() as u64 + 1;
+        self.images.push(Image {
+            id: image_id,
+            file_name,
+            width,
+            height,
+        });
+        for component in components {
+            self.annotations.push(Annotation {
+                id: self.annotations.len() as u64 + 1,
+                image_id,
+                category_id: component.label,
+                bbox: component.bbox,
+                area: component.area,
+                segmentation: vec![component.outline.iter().flatten().copied().collect()],
+                iscrowd: 0,
+            });
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("the dataset serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[test]
+    fn pixel_coordinates() {
+        let mut coco = CocoDataset::new(&Categories::new([(2, "vessel")]));
+        let component = Component {
+            label: 2,
+            area: 3,
+            bbox: [0, 0, 2, 2],
+            outline: vec![[0, 0], [2, 0], [2, 2], [1, 2], [1, 1], [0, 1]],
+        };
+        coco.add_image("a/0000.png".to_string(), [4, 3], &[]);
+        coco.add_image(
+            "a/0001.png".to_string(),
+            [4, 3],
+            &[component.clone(), component],
+        );
+
+        let json: Value = serde_json::from_slice(&coco.to_json()).unwrap();
+        assert_eq!(
+            json["images"][1],
+            json!({"id": 2, "file_name": "a/0001.png", "width": 4, "height": 3})
+        );
+        assert_eq!(
+            json["annotations"][1],
+            json!({
+                "id": 2,
+                "image_id": 2,
+                "category_id": 2,
+                "bbox": [0, 0, 2, 2],
+                "area": 3,
+                "segmentation": [[0, 0, 2, 0, 2, 2, 1, 2, 1, 1, 0, 1]],
+                "iscrowd": 0,
+            })
+        );
+        assert_eq!(json["categories"], json!([{"id": 2, "name": "vessel"}]));
+    }
+}
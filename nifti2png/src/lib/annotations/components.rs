@@ -0,0 +1,195 @@
+//! Connected components of the labels of a slice, in the coordinates of the exported PNG
+
+use crate::orientation::orient;
+
+/// 8-connected region of pixels sharing a label
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Component {
+    pub(crate) label: u32,
+    /// Number of pixels
+    pub(crate) area: usize,
+    /// `[x, y, width, height]` of the pixels
+    pub(crate) bbox: [usize; 4],
+    /// Corners of the pixels along the outer boundary, clockwise from the top left one.
+    /// Holes are not described.
+    pub(crate) outline: Vec<[usize; 2]>,
+}
+
+/// Labels laid out like the pixels of the exported PNG, see [`orientation`](crate::orientation)
+pub(crate) struct LabelGrid {
+    labels: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+/// East, south, west and north, so turning right is the next direction
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+impl LabelGrid {
+    /// Orients the C-ordered labels of a `[height, width]` slice.
+    /// The pixels without a voxel are background.
+    pub(crate) fn new(voxels: &[u32], [height, width]: [usize; 2]) -> Self {
+        Self {
+            labels: orient(voxels, [height, width]),
+            width,
+            height,
+        }
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.height
+    }
+
+    fn get(&self, x: isize, y: isize) -> Option<u32> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some(self.labels[y as usize * self.width + x as usize])
+    }
+
+    /// Components of the non-zero labels in the order of their top left pixels
+    pub(crate) fn components(&self) -> Vec<Component> {
+        let mut visited = vec![false; self.labels.len()];
+        let mut components = Vec::new();
+        for start in 0..self.labels.len() {
+            let label = self.labels[start];
+            if label == 0 || visited[start] {
+                continue;
+            }
+            let (x0, y0) = (start % self.width, start / self.width);
+            let (mut min, mut max) = ([x0, y0], [x0, y0]);
+            let mut area = 0;
+            let mut stack = vec![start];
+            visited[start] = true;
+            while let Some(i) = stack.pop() {
+                let (x, y) = (i % self.width, i / self.width);
+                area += 1;
+                min = [min[0].min(x), min[1].min(y)];
+                max = [max[0].max(x), max[1].max(y)];
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        if self.get(nx, ny) == Some(label) {
+                            let n = ny as usize * self.width + nx as usize;
+                            if !visited[n] {
+                                visited[n] = true;
+                                stack.push(n);
+                            }
+                        }
+                    }
+                }
+            }
+            components.push(Component {
+                label,
+                area,
+                bbox: [min[0], min[1], max[0] - min[0] + 1, max[1] - min[1] + 1],
+                outline: self.outline(label, [x0, y0]),
+            });
+        }
+        components
+    }
+
+    /// Follows the edges of the pixels around the component whose top left pixel is `start`,
+    /// keeping the component on the right
+    fn outline(&self, label: u32, start: [usize; 2]) -> Vec<[usize; 2]> {
+        let inside = |(x, y): (isize, isize)| self.get(x, y) == Some(label);
+        let start = (start[0] as isize, start[1] as isize);
+        let (mut vertex, mut direction) = (start, 0);
+        let mut outline = vec![[start.0 as usize, start.1 as usize]];
+        loop {
+            let (dx, dy) = DIRECTIONS[direction];
+            vertex = (vertex.0 + dx, vertex.1 + dy);
+            let (x, y) = vertex;
+            // The pixels ahead of the vertex on the left and on the right
+            let (ahead_left, ahead_right) = match direction {
+                0 => ((x, y - 1), (x, y)),
+                1 => ((x, y), (x - 1, y)),
+                2 => ((x - 1, y), (x - 1, y - 1)),
+                _ => ((x - 1, y - 1), (x, y - 1)),
+            };
+            // A diagonal neighbour belongs to the component too
+            let next = match (inside(ahead_left), inside(ahead_right)) {
+                (true, _) => (direction + 3) % 4,
+                (false, true) => direction,
+                (false, false) => (direction + 1) % 4,
+            };
+            if vertex == start && next == 0 {
+                return outline;
+            }
+            if next != direction {
+                outline.push([x as usize, y as usize]);
+            }
+            direction = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Grid of the labels as they appear in the PNG, row by row
+    fn grid(rows: &[&[u32]]) -> LabelGrid {
+        LabelGrid {
+            labels: rows.concat(),
+            width: rows[0].len(),
+            height: rows.len(),
+        }
+    }
+
+    #[test]
+    fn bbox_and_outline() {
+        let components = grid(&[&[1, 1, 0, 2], &[0, 1, 0, 2], &[3, 0, 0, 0]]).components();
+        assert_eq!(
+            components,
+            [
+                Component {
+                    label: 1,
+                    area: 3,
+                    bbox: [0, 0, 2, 2],
+                    outline: vec![[0, 0], [2, 0], [2, 2], [1, 2], [1, 1], [0, 1]],
+                },
+                Component {
+                    label: 2,
+                    area: 2,
+                    bbox: [3, 0, 1, 2],
+                    outline: vec![[3, 0], [4, 0], [4, 2], [3, 2]],
+                },
+                Component {
+                    label: 3,
+                    area: 1,
+                    bbox: [0, 2, 1, 1],
+                    outline: vec![[0, 2], [1, 2], [1, 3], [0, 3]],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diagonal_neighbours() {
+        let components = grid(&[&[1, 0], &[0, 1]]).components();
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].area, 2);
+        assert_eq!(components[0].bbox, [0, 0, 2, 2]);
+        // through the shared corner twice
+        assert_eq!(
+            components[0].outline,
+            [
+                [0, 0],
+                [1, 0],
+                [1, 1],
+                [2, 1],
+                [2, 2],
+                [1, 2],
+                [1, 1],
+                [0, 1]
+            ]
+        );
+        // the same label elsewhere is another component
+        assert_eq!(grid(&[&[1, 0, 1]]).components().len(), 2);
+    }
+}
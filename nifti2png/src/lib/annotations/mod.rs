@@ -0,0 +1,320 @@
+//! Object detection annotations of the slices exported by [`convert`](crate::convert),
+//! see [`export_annotations`]
+
+use std::{
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use pyo3::prelude::*;
+
+mod categories;
+mod coco;
+mod components;
+mod options;
+mod yolo;
+pub use categories::Categories;
+pub use options::{AnnotationFormat, AnnotationOptions, AnnotationOptionsBuilder};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    volume_format::VolumeFormat,
+    volumes::{Volume, Volumes},
+    ConvertOptions, DirectorySink, OnError, OutputSink,
+};
+use coco::CocoDataset;
+use components::{Component, LabelGrid};
+
+/// Results of [`export_annotations`]
+#[derive(Debug, Default)]
+pub struct AnnotationReport {
+    /// Sources whose slices were annotated
+    pub annotated: Vec<PathBuf>,
+    /// Sources without a label volume
+    pub unpaired: Vec<PathBuf>,
+    /// Number of annotated components
+    pub annotations: usize,
+    /// Sources that failed along with the reason.
+    /// Always empty with [`OnError::Stop`].
+    pub failed: Vec<(PathBuf, ErrorTy)>,
+}
+
+impl AnnotationReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for AnnotationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Annotated: {} ({} annotations), unpaired: {}, failed: {}",
+            self.annotated.len(),
+            self.annotations,
+            self.unpaired.len(),
+            self.failed.len()
+        )?;
+        for (source, e) in self.failed.iter() {
+            write!(f, "\n\t{}: {e}", source.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Annotated slice of a volume
+struct SliceAnnotations {
+    z: usize,
+    // [width, height] of the PNG
+    size: [usize; 2],
+    components: Vec<Component>,
+}
+
+/// Annotates the slices that [`convert`](crate::convert) exports with the same `convert_options`
+/// with the connected components of the label volumes: their boxes and their outlines.
+///
+/// The label volume of `<nii_files>/<dir>/<name>.<ext>` is `<labels>/<dir>/<name><label_suffix>.<ext>`
+/// with any supported extension. The components are 8-connected and taken from the same slices
+/// as the PNGs, i.e. from the last volume of 4D label volumes, and in the same orientation.
+/// The labels without a category are left out.
+///
+/// The PNGs themselves are neither needed nor read, so the annotations can be exported
+/// before or after the conversion.
+pub fn export_annotations(
+    convert_options: &ConvertOptions,
+    options: &AnnotationOptions,
+) -> Result<AnnotationReport, ErrorTy> {
+    Python::with_gil(|py| {
+        let res = annotate(py, convert_options, options);
+        if let Err(e) = &res {
+            tracing::error!(error = %e, "The annotation failed");
+        }
+        res
+    })
+}
+
+fn annotate(
+    py: Python<'_>,
+    convert_options: &ConvertOptions,
+    options: &AnnotationOptions,
+) -> Result<AnnotationReport, ErrorTy> {
+    let (nii_files, png_stub) = (convert_options.nii_files(), convert_options.png_stub());
+    // With a suffix, the labels may be stored along with the images
+    let same_dir = options.labels == nii_files;
+    if same_dir && options.label_suffix.is_empty() {
+        return Err(EmptyLabelSuffix);
+    }
+    let sink: Arc<dyn OutputSink> = options
+        .output_sink
+        .clone()
+        .unwrap_or_else(|| Arc::new(DirectorySink));
+    let np = py.import("numpy").map_err(MissingThirdPartyLibrary)?;
+
+    let mut report = AnnotationReport::default();
+    let mut coco = match options.format {
+        AnnotationFormat::Coco => Some(CocoDataset::new(&options.categories)),
+        AnnotationFormat::Yolo | AnnotationFormat::YoloSegments => None,
+    };
+    let mut volumes = Volumes::new(py, convert_options)?;
+    loop {
+        // SAFETY: only the annotations leave the iteration
+        let volume_gil_pool = unsafe { py.new_pool() };
+        let py = volume_gil_pool.python();
+
+        let Some(res) = volumes.next() else {
+            break;
+        };
+        let volume = match res {
+            Ok(volume) => volume,
+            Err(e) => {
+                record_failure(e, convert_options.on_error(), &mut report)?;
+                continue;
+            }
+        };
+        let source = volume.source().to_path_buf();
+        let rel_path = source.strip_prefix(nii_files).unwrap_or(&source);
+        if same_dir && is_label(rel_path, &options.label_suffix) {
+            continue;
+        }
+        let _volume_span = tracing::info_span!("volume", source = %source.display()).entered();
+
+        let res = find_label(options, rel_path).and_then(|label| match label {
+            Some((label_dir, label_entry)) => {
+                let label = Volume::open(py, &label_dir, &label_entry)?;
+                annotate_volume(py, np, &volume, &label, options).map(Some)
+            }
+            None => Ok(None),
+        });
+        let slices = match res {
+            Ok(Some(slices)) => slices,
+            Ok(None) => {
+                tracing::warn!("No label volume");
+                report.unpaired.push(source);
+                continue;
+            }
+            Err(e) => {
+                record_failure(e.in_file(source), convert_options.on_error(), &mut report)?;
+                continue;
+            }
+        };
+
+        let target_dir = volume.target_dir();
+        for slice in slices.iter() {
+            let png = target_dir.join(format!("{:04}.png", slice.z));
+            match &mut coco {
+                Some(coco) => {
+                    let file_name = png.strip_prefix(png_stub).unwrap_or(&png);
+                    coco.add_image(
+                        file_name.display().to_string(),
+                        slice.size,
+                        &slice.components,
+                    );
+                }
+                None => {
+                    let lines = match options.format {
+                        AnnotationFormat::YoloSegments => {
+                            yolo::segments(&options.categories, slice.size, &slice.components)
+                        }
+                        _ => yolo::boxes(&options.categories, slice.size, &slice.components),
+                    };
+                    sink.write(&png.with_extension("txt"), lines.as_bytes())?;
+                }
+            }
+            report.annotations += slice.components.len();
+        }
+        tracing::info!(slices = slices.len(), "Annotated the volume");
+        report.annotated.push(source);
+    }
+
+    match coco {
+        Some(coco) => sink.write(&png_stub.join("annotations.json"), &coco.to_json())?,
+        None => sink.write(
+            &png_stub.join("classes.txt"),
+            yolo::classes(&options.categories).as_bytes(),
+        )?,
+    }
+    sink.finish()?;
+    Ok(report)
+}
+
+/// Whether the name of the volume without the extension ends with the suffix
+fn is_label(rel_path: &Path, label_suffix: &str) -> bool {
+    let name = rel_path.file_name().unwrap_or_default();
+    let stem = VolumeFormat::stem(name).unwrap_or_else(|| name.to_os_string());
+    stem.to_string_lossy().ends_with(label_suffix)
+}
+
+/// Directory and entry of the label volume of the volume at `rel_path` relative to `nii_files`
+fn find_label(
+    options: &AnnotationOptions,
+    rel_path: &Path,
+) -> Result<Option<(PathBuf, OsString)>, ErrorTy> {
+    let label_dir = match rel_path.parent() {
+        Some(parent) => options.labels.join(parent),
+        None => options.labels.clone(),
+    };
+    let name = rel_path.file_name().unwrap_or_default();
+    // DICOM series folders have no extension
+    let mut label_stem = VolumeFormat::stem(name).unwrap_or_else(|| name.to_os_string());
+    label_stem.push(&options.label_suffix);
+
+    let read_dir_failed = |e| ReadDirFailed(e, label_dir.display().to_string());
+    let entries = match std::fs::read_dir(&label_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(read_dir_failed(e)),
+    };
+    let mut labels = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(read_dir_failed)?.file_name();
+        if VolumeFormat::stem(&entry).as_deref() == Some(label_stem.as_os_str()) {
+            labels.push(entry);
+        }
+    }
+    match labels.len() {
+        0 => Ok(None),
+        1 => Ok(Some((label_dir, labels.remove(0)))),
+        _ => Err(AmbiguousPair(
+            labels
+                .iter()
+                .map(|entry| entry.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )),
+    }
+}
+
+/// Components of the labels of every slice of the PNGs of the volume
+fn annotate_volume<'py>(
+    py: Python<'py>,
+    np: &'py PyModule,
+    volume: &Volume<'py>,
+    label: &Volume<'py>,
+    options: &AnnotationOptions,
+) -> Result<Vec<SliceAnnotations>, ErrorTy> {
+    let (image_shape, label_shape) = (&volume.header().shape, &label.header().shape);
+    let [z_count, t_count] = volume.slice_counts();
+    let [label_z_count, label_t_count] = label.slice_counts();
+    if image_shape[..3] != label_shape[..3]
+        || z_count != label_z_count
+        || (label_t_count != 1 && label_t_count != t_count)
+    {
+        return Err(LabelShapeMismatch(
+            label.source().display().to_string(),
+            label_shape.clone(),
+            image_shape.clone(),
+        ));
+    }
+
+    let mut slices = Vec::new();
+    for z in 0..z_count {
+        // SAFETY: only the copied labels leave the iteration
+        let slice_gil_pool = unsafe { py.new_pool() };
+        let py = slice_gil_pool.python();
+
+        // The PNGs of 4D volumes hold their last volume
+        let slice = label.slice(py, [z, label_t_count - 1])?;
+        let (height, width) = slice.getattr("shape")?.extract::<(usize, usize)>()?;
+        let voxels: Vec<u32> = np
+            .call_method1("rint", (slice,))?
+            .call_method1("clip", (0, u32::MAX))?
+            .call_method1("astype", ("uint32",))?
+            .call_method0("ravel")?
+            .call_method0("tolist")?
+            .extract()?;
+        let grid = LabelGrid::new(&voxels, [height, width]);
+        let components = grid
+            .components()
+            .into_iter()
+            .filter(|component| {
+                component.area >= options.min_area
+                    && options.categories.name(component.label).is_some()
+            })
+            .collect();
+        slices.push(SliceAnnotations {
+            z,
+            size: [grid.width(), grid.height()],
+            components,
+        });
+    }
+    Ok(slices)
+}
+
+/// Records the failure of a single source in the report with [`OnError::Continue`].
+/// Returns the error otherwise or if it doesn't belong to a single source.
+fn record_failure(
+    e: ErrorTy,
+    on_error: OnError,
+    report: &mut AnnotationReport,
+) -> Result<(), ErrorTy> {
+    match (e, on_error) {
+        (FileFailed(source, e), OnError::Continue) => {
+            tracing::warn!(source = %source.display(), error = %e, "Failed to annotate the file");
+            report.failed.push((source, *e));
+            Ok(())
+        }
+        (e, _) => Err(e),
+    }
+}
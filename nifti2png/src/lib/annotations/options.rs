@@ -0,0 +1,103 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::Categories;
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    OutputSink,
+};
+
+/// Format of the annotations written by [`export_annotations`](crate::export_annotations)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnnotationFormat {
+    /// `<png_stub>/annotations.json` with the boxes and the polygons of all slices
+    #[default]
+    Coco,
+    /// `<z>.txt` next to every `<z>.png` with a `class cx cy w h` line per box,
+    /// and `<png_stub>/classes.txt` with the names of the classes
+    Yolo,
+    /// Same as [`Yolo`](Self::Yolo) but with a `class x1 y1 x2 y2 ...` line per polygon
+    YoloSegments,
+}
+
+/// Settings of [`export_annotations`](crate::export_annotations), created with [`AnnotationOptions::builder`]
+#[derive(Clone, Debug)]
+pub struct AnnotationOptions {
+    pub(crate) labels: PathBuf,
+    pub(crate) label_suffix: String,
+    pub(crate) categories: Categories,
+    pub(crate) format: AnnotationFormat,
+    pub(crate) min_area: usize,
+    pub(crate) output_sink: Option<Arc<dyn OutputSink>>,
+}
+
+/// Builder of [`AnnotationOptions`]
+#[derive(Clone, Debug)]
+pub struct AnnotationOptionsBuilder(AnnotationOptions);
+
+impl AnnotationOptions {
+    /// Starts building the options for annotating the slices with the label volumes
+    /// of the `labels` directory, which mirrors the directory of the images
+    pub fn builder(labels: impl AsRef<Path>, categories: Categories) -> AnnotationOptionsBuilder {
+        AnnotationOptionsBuilder(AnnotationOptions {
+            labels: labels.as_ref().to_path_buf(),
+            label_suffix: String::new(),
+            categories,
+            format: AnnotationFormat::default(),
+            min_area: 1,
+            output_sink: None,
+        })
+    }
+
+    pub fn labels(&self) -> &Path {
+        &self.labels
+    }
+
+    pub fn categories(&self) -> &Categories {
+        &self.categories
+    }
+
+    pub fn format(&self) -> AnnotationFormat {
+        self.format
+    }
+}
+
+impl AnnotationOptionsBuilder {
+    /// Suffix that the names of the label volumes have in addition to the names of the images,
+    /// see [`DatasetOptionsBuilder::label_suffix`](crate::DatasetOptionsBuilder::label_suffix)
+    pub fn label_suffix(mut self, label_suffix: impl Into<String>) -> Self {
+        self.0.label_suffix = label_suffix.into();
+        self
+    }
+
+    pub fn format(mut self, format: AnnotationFormat) -> Self {
+        self.0.format = format;
+        self
+    }
+
+    /// Smallest number of pixels of an annotated component, e.g. to leave out noise. 1 by default.
+    pub fn min_area(mut self, min_area: usize) -> Self {
+        self.0.min_area = min_area;
+        self
+    }
+
+    /// Writes the annotations to the sink instead of the directories,
+    /// see [`ConvertOptionsBuilder::output_sink`](crate::ConvertOptionsBuilder::output_sink)
+    pub fn output_sink(mut self, sink: Arc<dyn OutputSink>) -> Self {
+        self.0.output_sink = Some(sink);
+        self
+    }
+
+    pub fn build(self) -> Result<AnnotationOptions, ErrorTy> {
+        let options = self.0;
+        if options.labels.as_os_str().is_empty() {
+            return Err(EmptyPath("labels"));
+        }
+        if options.categories.is_empty() {
+            return Err(NoCategories);
+        }
+        Ok(options)
+    }
+}
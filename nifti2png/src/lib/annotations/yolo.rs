@@ -0,0 +1,103 @@
+//! [YOLO](https://docs.ultralytics.com/datasets/detect/) label files, one per PNG,
+//! with the coordinates normalized to the size of the image
+
+use std::fmt::Write;
+
+use super::{components::Component, Categories};
+
+/// Label file of a `[width, height]` PNG with a `class cx cy w h` line per component
+pub(crate) fn boxes(
+    categories: &Categories,
+    [width, height]: [usize; 2],
+    components: &[Component],
+) -> String {
+    let (width, height) = (width as f64, height as f64);
+    let mut lines = String::new();
+    for component in components {
+        let Some(class) = categories.index(component.label) else {
+            continue;
+        };
+        let [x, y, w, h] = component.bbox.map(|v| v as f64);
+        writeln!(
+            lines,
+            "{class} {:.6} {:.6} {:.6} {:.6}",
+            (x + w / 2.0) / width,
+            (y + h / 2.0) / height,
+            w / width,
+            h / height
+        )
+        .expect("writing to a String doesn't fail");
+    }
+    lines
+}
+
+/// Label file of a `[width, height]` PNG with a `class x1 y1 x2 y2 ...` line per component
+pub(crate) fn segments(
+    categories: &Categories,
+    [width, height]: [usize; 2],
+    components: &[Component],
+) -> String {
+    let mut lines = String::new();
+    for component in components {
+        let Some(class) = categories.index(component.label) else {
+            continue;
+        };
+        write!(lines, "{class}").expect("writing to a String doesn't fail");
+        for [x, y] in component.outline.iter() {
+            write!(
+                lines,
+                " {:.6} {:.6}",
+                *x as f64 / width as f64,
+                *y as f64 / height as f64
+            )
+            .expect("writing to a String doesn't fail");
+        }
+        lines.push('\n');
+    }
+    lines
+}
+
+/// `classes.txt` with the names of the classes in the order of their indices
+pub(crate) fn classes(categories: &Categories) -> String {
+    categories
+        .iter()
+        .map(|(_, name)| format!("{name}\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components() -> Vec<Component> {
+        vec![
+            Component {
+                label: 2,
+                area: 2,
+                bbox: [3, 0, 1, 2],
+                outline: vec![[3, 0], [4, 0], [4, 2], [3, 2]],
+            },
+            // no name
+            Component {
+                label: 3,
+                area: 1,
+                bbox: [0, 2, 1, 1],
+                outline: vec![[0, 2], [1, 2], [1, 3], [0, 3]],
+            },
+        ]
+    }
+
+    #[test]
+    fn normalized_coordinates() {
+        let categories = Categories::new([(1, "nodule"), (2, "vessel")]);
+        assert_eq!(
+            boxes(&categories, [4, 3], &components()),
+            "1 0.875000 0.333333 0.250000 0.666667\n"
+        );
+        assert_eq!(
+            segments(&categories, [4, 3], &components()),
+            "1 0.750000 0.000000 1.000000 0.000000 1.000000 0.666667 0.750000 0.666667\n"
+        );
+        assert_eq!(classes(&categories), "nodule\nvessel\n");
+    }
+}
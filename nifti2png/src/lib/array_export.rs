@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// Arrays written by [`convert`](crate::convert) instead of the PNGs,
+/// see [`ConvertOptionsBuilder::array_export`](crate::ConvertOptionsBuilder::array_export)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArrayExport {
+    pub dtype: ArrayDtype,
+    pub layout: ArrayLayout,
+    pub container: ArrayContainer,
+}
+
+/// Type of the elements of the exported arrays.
+/// The rescaled intensities from 0 to 1 are stored as is or mapped to the whole range of the integers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArrayDtype {
+    /// The intensities from 0 to 1 without any loss
+    #[default]
+    Float32,
+    /// The intensities rounded to 0-65535
+    Uint16,
+    /// The same values as the PNGs
+    Uint8,
+}
+
+/// What a single array holds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArrayLayout {
+    /// A `[height, width]` array per slice named `<z>`, or `<t>_<z>` for 4D volumes.
+    /// Unlike the PNGs, every volume of 4D volumes is kept.
+    #[default]
+    Slices,
+    /// A single array named `volume` with the slices of the volume:
+    /// `[z, height, width]`, or `[t, z, height, width]` for 4D volumes
+    Volume,
+}
+
+/// File format of the exported arrays
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArrayContainer {
+    /// A `<name>.npy` file per array
+    #[default]
+    Npy,
+    /// All arrays of the volume in a compressed `slices.npz` or `volume.npz`,
+    /// like `numpy.savez_compressed` writes
+    Npz,
+}
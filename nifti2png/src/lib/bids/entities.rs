@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use crate::volume_format::VolumeFormat;
+
+/// Entities of a BIDS file name, e.g. `sub-01`, `ses-02` and the suffix `T1w`
+/// of `sub-01_ses-02_T1w.nii.gz`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BidsEntities {
+    // key-value pairs in the order of the name
+    pairs: Vec<(String, String)>,
+    suffix: String,
+}
+
+impl BidsEntities {
+    /// Parses the name without the extension. Returns `None` unless it is
+    /// a sequence of `<key>-<value>` pairs followed by the suffix, joined with `_`.
+    pub(crate) fn parse(stem: &str) -> Option<Self> {
+        let mut pairs = Vec::new();
+        let mut parts = stem.split('_').peekable();
+        while let Some(part) = parts.next() {
+            match part.split_once('-') {
+                Some((key, value)) if !key.is_empty() && !value.is_empty() => {
+                    pairs.push((key.to_string(), value.to_string()))
+                }
+                None if parts.peek().is_none() && !part.is_empty() => {
+                    return Some(Self {
+                        pairs,
+                        suffix: part.to_string(),
+                    })
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// Entities of the volume, which must name a subject
+    pub(crate) fn of_volume(path: &Path) -> Option<Self> {
+        let stem = VolumeFormat::stem(path.file_name()?)?;
+        Self::parse(stem.to_str()?).filter(|entities| entities.get("sub").is_some())
+    }
+
+    /// Entities of the JSON sidecar
+    pub(crate) fn of_sidecar(path: &Path) -> Option<Self> {
+        let stem = path.file_name()?.to_str()?.strip_suffix(".json")?;
+        Self::parse(stem)
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn suffix(&self) -> &str {
+        &self.suffix
+    }
+
+    /// Whether the sidecar with these entities applies to the file with `other`
+    /// according to the inheritance principle: the suffix is the same
+    /// and all the entities are those of the file
+    pub(crate) fn applies_to(&self, other: &BidsEntities) -> bool {
+        self.suffix == other.suffix
+            && self
+                .pairs
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pairs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let entities = BidsEntities::parse("sub-01_ses-pre_acq-1.5T_T1w").unwrap();
+        assert_eq!(entities.get("sub"), Some("01"));
+        assert_eq!(entities.get("ses"), Some("pre"));
+        assert_eq!(entities.get("acq"), Some("1.5T"));
+        assert_eq!(entities.get("run"), None);
+        assert_eq!(entities.suffix(), "T1w");
+        assert_eq!(entities.len(), 3);
+
+        assert_eq!(BidsEntities::parse("T1w").unwrap().len(), 0);
+        for stem in [
+            "",
+            "sub-01",
+            "sub-01_",
+            "sub-_T1w",
+            "-01_T1w",
+            "sub-01__T1w",
+            "a_b",
+        ] {
+            assert_eq!(BidsEntities::parse(stem), None, "{stem}");
+        }
+    }
+
+    #[test]
+    fn of_volume_and_sidecar() {
+        let volume = BidsEntities::of_volume(Path::new("sub-01/anat/sub-01_T1w.nii.gz")).unwrap();
+        assert_eq!(volume.get("sub"), Some("01"));
+        assert_eq!(volume.suffix(), "T1w");
+        // no subject
+        assert_eq!(
+            BidsEntities::of_volume(Path::new("task-rest_bold.nii")),
+            None
+        );
+        assert_eq!(BidsEntities::of_volume(Path::new("sub-01_T1w.json")), None);
+
+        let sidecar = BidsEntities::of_sidecar(Path::new("task-rest_bold.json")).unwrap();
+        assert_eq!(sidecar.get("task"), Some("rest"));
+        assert_eq!(BidsEntities::of_sidecar(Path::new("sub-01_T1w.nii")), None);
+    }
+
+    #[test]
+    fn applies_to() {
+        let volume = BidsEntities::parse("sub-01_task-rest_run-1_bold").unwrap();
+        for sidecar in ["bold", "task-rest_bold", "sub-01_run-1_bold"] {
+            assert!(
+                BidsEntities::parse(sidecar).unwrap().applies_to(&volume),
+                "{sidecar}"
+            );
+        }
+        for sidecar in ["T1w", "task-motor_bold", "sub-01_echo-1_bold"] {
+            assert!(
+                !BidsEntities::parse(sidecar).unwrap().applies_to(&volume),
+                "{sidecar}"
+            );
+        }
+    }
+}
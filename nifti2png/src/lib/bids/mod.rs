@@ -0,0 +1,144 @@
+//! Datasets in the [Brain Imaging Data Structure](https://bids.neuroimaging.io),
+//! see [`ConvertOptionsBuilder::bids`](crate::ConvertOptionsBuilder::bids)
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+mod entities;
+mod sidecar;
+pub(crate) use entities::BidsEntities;
+pub(crate) use sidecar::sidecar_path;
+
+use crate::error_ty::ErrorTy::{self, *};
+
+/// Name of the pipeline in the metadata of the derivatives
+const PIPELINE: &str = "nifti2png";
+/// Name of the converted dataset in the BIDS URIs of the derivatives
+const RAW: &str = "raw";
+
+/// Entities of the volumes of a BIDS dataset to convert. Empty lists accept all values,
+/// otherwise the volumes without the entity are rejected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BidsFilter {
+    /// Labels of `sub-<label>`, e.g. `01`
+    pub subjects: Vec<String>,
+    /// Labels of `ses-<label>`
+    pub sessions: Vec<String>,
+    /// Labels of `task-<label>`, e.g. `rest`
+    pub tasks: Vec<String>,
+    /// Indices of `run-<index>`, compared as numbers, so `1` accepts `run-01`
+    pub runs: Vec<String>,
+    /// Suffixes, e.g. `T1w` or `bold`
+    pub suffixes: Vec<String>,
+}
+
+impl BidsFilter {
+    /// Whether the volume at the path relative to the root of the dataset is accepted
+    pub(crate) fn accepts(&self, rel_path: &OsStr) -> bool {
+        let Some(entities) = BidsEntities::of_volume(Path::new(rel_path)) else {
+            return false;
+        };
+        let accepts = |labels: &[String], label: Option<&str>| {
+            labels.is_empty() || label.is_some_and(|label| labels.iter().any(|l| l == label))
+        };
+        let accepts_run = |run: Option<&str>| {
+            self.runs.is_empty()
+                || run.is_some_and(|run| {
+                    self.runs
+                        .iter()
+                        .any(|r| match (r.parse::<u64>(), run.parse::<u64>()) {
+                            (Ok(r), Ok(run)) => r == run,
+                            _ => r == run,
+                        })
+                })
+        };
+        accepts(&self.subjects, entities.get("sub"))
+            && accepts(&self.sessions, entities.get("ses"))
+            && accepts(&self.tasks, entities.get("task"))
+            && accepts_run(entities.get("run"))
+            && accepts(&self.suffixes, Some(entities.suffix()))
+    }
+}
+
+/// Collects the paths of the volumes in the folder, relative to the root
+fn walk(root: &Path, rel_dir: &Path, volumes: &mut Vec<OsString>) -> Result<(), ErrorTy> {
+    let dir = root.join(rel_dir);
+    let read_dir_failed = |e| ReadDirFailed(e, dir.display().to_string());
+    for entry in std::fs::read_dir(&dir).map_err(read_dir_failed)? {
+        let entry = entry.map_err(read_dir_failed)?;
+        let name = entry.file_name();
+        // e.g. the temporary files of the atomic writes
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let rel_path = rel_dir.join(&name);
+        if entry.path().is_dir() {
+            walk(root, &rel_path, volumes)?;
+        } else if BidsEntities::of_volume(&rel_path).is_some() {
+            volumes.push(rel_path.into_os_string());
+        }
+    }
+    Ok(())
+}
+
+/// Paths of all volumes of the dataset relative to its root, e.g. `sub-01/ses-02/anat/sub-01_ses-02_T1w.nii.gz`,
+/// in the order of the filesystem. Only the `sub-*` folders are searched,
+/// so `derivatives`, `sourcedata` and the like are left out.
+pub(crate) fn discover(root: &Path) -> Result<Vec<OsString>, ErrorTy> {
+    let mut volumes = Vec::new();
+    for entry in
+        std::fs::read_dir(root).map_err(|e| ReadDirFailed(e, root.display().to_string()))?
+    {
+        let entry = entry.map_err(|e| ReadDirFailed(e, root.display().to_string()))?;
+        if entry.file_name().to_string_lossy().starts_with("sub-") && entry.path().is_dir() {
+            walk(root, Path::new(&entry.file_name()), &mut volumes)?;
+        }
+    }
+    Ok(volumes)
+}
+
+/// `dataset_description.json` of the derivatives of the dataset
+pub(crate) fn dataset_description(root: &Path) -> Result<Vec<u8>, ErrorTy> {
+    let raw_path = root.join("dataset_description.json");
+    let raw = match std::fs::read(&raw_path) {
+        Ok(bytes) => serde_json::from_slice::<Value>(&bytes)
+            .map_err(|e| SidecarReadFailed(e.into(), raw_path.display().to_string()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Value::Null,
+        Err(e) => return Err(SidecarReadFailed(e, raw_path.display().to_string())),
+    };
+    let name = match raw.get("Name").and_then(Value::as_str) {
+        Some(name) => format!("{name} ({PIPELINE})"),
+        None => PIPELINE.to_string(),
+    };
+    let root = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let description = json!({
+        "Name": name,
+        "BIDSVersion": raw.get("BIDSVersion").cloned().unwrap_or_else(|| json!("1.8.0")),
+        "DatasetType": "derivative",
+        "GeneratedBy": [{
+            "Name": PIPELINE,
+            "Version": env!("CARGO_PKG_VERSION"),
+        }],
+        "DatasetLinks": {
+            RAW: format!("file://{}", root.display()),
+        },
+    });
+    Ok(serde_json::to_vec_pretty(&description).expect("JSON values serialize"))
+}
+
+/// Sidecar of the slices of the volume: the metadata of the volume merged from its sidecars,
+/// along with the BIDS URI of the volume in `Sources`
+pub(crate) fn derivative_sidecar(root: &Path, source: &Path) -> Result<Vec<u8>, ErrorTy> {
+    let rel_path = source.strip_prefix(root).unwrap_or(source);
+    let mut metadata = sidecar::merged_metadata(root, rel_path)?;
+    metadata.insert(
+        "Sources".to_string(),
+        json!([format!("bids:{RAW}:{}", rel_path.display())]),
+    );
+    Ok(serde_json::to_vec_pretty(&metadata).expect("JSON values serialize"))
+}
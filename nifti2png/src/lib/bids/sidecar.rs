@@ -0,0 +1,120 @@
+use std::{ffi::OsString, path::Path};
+
+use serde_json::{Map, Value};
+
+use super::entities::BidsEntities;
+use crate::error_ty::ErrorTy::{self, *};
+
+type Metadata = Map<String, Value>;
+
+/// Reads the JSON object of the sidecar
+fn read_sidecar(path: &Path) -> Result<Metadata, ErrorTy> {
+    let read_failed = |e: std::io::Error| SidecarReadFailed(e, path.display().to_string());
+    let bytes = std::fs::read(path).map_err(read_failed)?;
+    match serde_json::from_slice(&bytes).map_err(|e| read_failed(e.into()))? {
+        Value::Object(metadata) => Ok(metadata),
+        _ => Err(read_failed(std::io::Error::other("not a JSON object"))),
+    }
+}
+
+/// Metadata of the volume at `rel_path` within the dataset `root`.
+///
+/// The sidecars are collected according to the inheritance principle of BIDS: those in the folders
+/// from the root down to the one of the volume whose entities are a subset of the entities
+/// of the volume. The deeper and more specific sidecars override the keys of the others.
+pub(crate) fn merged_metadata(root: &Path, rel_path: &Path) -> Result<Metadata, ErrorTy> {
+    let Some(entities) = BidsEntities::of_volume(rel_path) else {
+        return Ok(Metadata::new());
+    };
+    let mut dir = root.to_path_buf();
+    let mut dirs = vec![dir.clone()];
+    if let Some(parent) = rel_path.parent() {
+        for component in parent.components() {
+            dir.push(component);
+            dirs.push(dir.clone());
+        }
+    }
+
+    let mut metadata = Metadata::new();
+    for dir in dirs {
+        let mut sidecars = Vec::new();
+        for entry in
+            std::fs::read_dir(&dir).map_err(|e| ReadDirFailed(e, dir.display().to_string()))?
+        {
+            let path = entry
+                .map_err(|e| ReadDirFailed(e, dir.display().to_string()))?
+                .path();
+            match BidsEntities::of_sidecar(&path) {
+                Some(sidecar) if sidecar.applies_to(&entities) => {
+                    sidecars.push((sidecar.len(), path))
+                }
+                _ => (),
+            }
+        }
+        sidecars.sort();
+        for (_, path) in sidecars {
+            metadata.extend(read_sidecar(&path)?);
+        }
+    }
+    Ok(metadata)
+}
+
+/// Path of the sidecar of the exported directory: its path followed by `.json`.
+/// The names may contain dots, e.g. `acq-1.5T`, so the extension isn't replaced.
+pub(crate) fn sidecar_path(target_dir: &Path) -> std::path::PathBuf {
+    let mut path = OsString::from(target_dir.as_os_str());
+    path.push(".json");
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn deeper_and_more_specific_sidecars_win() {
+        let root =
+            std::env::temp_dir().join(format!("nifti2png-bids-sidecar-{}", std::process::id()));
+        let func = root.join("sub-01").join("func");
+        std::fs::create_dir_all(&func).unwrap();
+        for (path, metadata) in [
+            (
+                root.join("task-rest_bold.json"),
+                json!({"a": 1, "b": 1, "c": 1, "d": 1}),
+            ),
+            (root.join("task-motor_bold.json"), json!({"a": "motor"})),
+            (root.join("T1w.json"), json!({"a": "T1w"})),
+            (
+                root.join("sub-01/sub-01_task-rest_bold.json"),
+                json!({"b": 2, "c": 2}),
+            ),
+            (
+                func.join("sub-01_task-rest_run-1_bold.json"),
+                json!({"d": 4}),
+            ),
+            (
+                func.join("sub-01_task-rest_bold.json"),
+                json!({"c": 3, "d": 3}),
+            ),
+        ] {
+            std::fs::write(path, metadata.to_string()).unwrap();
+        }
+
+        let metadata = merged_metadata(
+            &root,
+            Path::new("sub-01/func/sub-01_task-rest_run-1_bold.nii.gz"),
+        )
+        .unwrap();
+        assert_eq!(
+            Value::Object(metadata),
+            json!({"a": 1, "b": 2, "c": 3, "d": 4})
+        );
+        // not BIDS
+        assert!(merged_metadata(&root, Path::new("brain.nii"))
+            .unwrap()
+            .is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    output_names::OutputNames,
+    ArrayExport, BidsFilter, ExportMode, InputOrder, ManifestFormat, MemoryBudget, NameCollision,
+    OnError, OutputSink, OverwritePolicy, VolumeAccess,
+};
+
+/// Settings of [`convert`](crate::convert), created with [`ConvertOptions::builder`]
+//
+// Serialized for the worker processes, which get the entries and the sink separately
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConvertOptions {
+    #[serde(with = "crate::paths::path_bytes")]
+    pub(crate) nii_files: PathBuf,
+    #[serde(skip)]
+    pub(crate) entries: Option<Vec<OsString>>,
+    pub(crate) input_order: InputOrder,
+    #[serde(with = "crate::paths::path_bytes")]
+    pub(crate) png_stub: PathBuf,
+    pub(crate) minmax: Option<(u64, u64)>,
+    pub(crate) export_mode: ExportMode,
+    pub(crate) volume_access: VolumeAccess,
+    pub(crate) memory_budget: Option<MemoryBudget>,
+    pub(crate) on_error: OnError,
+    pub(crate) resume: bool,
+    pub(crate) output_manifest: Option<ManifestFormat>,
+    pub(crate) overwrite: OverwritePolicy,
+    pub(crate) name_collision: NameCollision,
+    #[serde(skip)]
+    pub(crate) output_sink: Option<Arc<dyn OutputSink>>,
+    pub(crate) bids: Option<BidsFilter>,
+    pub(crate) array_export: Option<ArrayExport>,
+    // resolved in advance for the explicit `entries`, so that nii_files isn't listed again
+    #[serde(skip)]
+    pub(crate) output_names: Option<OutputNames>,
+}
+
+/// Builder of [`ConvertOptions`]
+#[derive(Clone, Debug)]
+pub struct ConvertOptionsBuilder(ConvertOptions);
+
+impl ConvertOptions {
+    /// Starts building the options for converting the volumes in the `nii_files` directory
+    pub fn builder(nii_files: impl AsRef<Path>) -> ConvertOptionsBuilder {
+        ConvertOptionsBuilder(ConvertOptions {
+            nii_files: nii_files.as_ref().to_path_buf(),
+            entries: None,
+            input_order: InputOrder::default(),
+            // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L61-L64
+            png_stub: PathBuf::from("slice"),
+            minmax: None,
+            export_mode: ExportMode::default(),
+            volume_access: VolumeAccess::default(),
+            memory_budget: None,
+            on_error: OnError::default(),
+            resume: false,
+            output_manifest: None,
+            overwrite: OverwritePolicy::default(),
+            name_collision: NameCollision::default(),
+            output_sink: None,
+            bids: None,
+            array_export: None,
+            output_names: None,
+        })
+    }
+
+    /// Builder starting from these options, e.g. the ones received by a worker process
+    pub(crate) fn into_builder(self) -> ConvertOptionsBuilder {
+        ConvertOptionsBuilder(self)
+    }
+
+    pub fn nii_files(&self) -> &Path {
+        &self.nii_files
+    }
+
+    pub fn entries(&self) -> Option<&[OsString]> {
+        self.entries.as_deref()
+    }
+
+    pub fn input_order(&self) -> InputOrder {
+        self.input_order
+    }
+
+    pub fn png_stub(&self) -> &Path {
+        &self.png_stub
+    }
+
+    pub fn minmax(&self) -> Option<(u64, u64)> {
+        self.minmax
+    }
+
+    pub fn export_mode(&self) -> ExportMode {
+        self.export_mode
+    }
+
+    pub fn volume_access(&self) -> VolumeAccess {
+        self.volume_access
+    }
+
+    pub fn memory_budget(&self) -> Option<MemoryBudget> {
+        self.memory_budget
+    }
+
+    pub fn on_error(&self) -> OnError {
+        self.on_error
+    }
+
+    pub fn resume(&self) -> bool {
+        self.resume
+    }
+
+    pub fn output_manifest(&self) -> Option<ManifestFormat> {
+        self.output_manifest
+    }
+
+    pub fn overwrite(&self) -> OverwritePolicy {
+        self.overwrite
+    }
+
+    pub fn name_collision(&self) -> NameCollision {
+        self.name_collision
+    }
+
+    pub fn bids(&self) -> Option<&BidsFilter> {
+        self.bids.as_ref()
+    }
+
+    pub fn array_export(&self) -> Option<ArrayExport> {
+        self.array_export
+    }
+
+    pub fn output_sink(&self) -> Option<&Arc<dyn OutputSink>> {
+        self.output_sink.as_ref()
+    }
+}
+
+impl ConvertOptionsBuilder {
+    /// Converts only the given entries of the directory
+    /// (names of files and DICOM series folders, as returned by `os.listdir`)
+    /// instead of all of them.
+    pub fn entries(mut self, entries: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        self.0.entries = Some(entries.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Order of the entries listed from the directory, natural by default.
+    /// Has no effect on the [`entries`](Self::entries) given explicitly.
+    pub fn input_order(mut self, order: InputOrder) -> Self {
+        self.0.input_order = order;
+        self
+    }
+
+    /// Directory where the directories with the slices of every volume are created.
+    /// `slice` by default.
+    pub fn png_stub(mut self, png_stub: impl AsRef<Path>) -> Self {
+        self.0.png_stub = png_stub.as_ref().to_path_buf();
+        self
+    }
+
+    /// Intensities mapped to black and white. The range of every volume by default.
+    pub fn minmax(mut self, min: u64, max: u64) -> Self {
+        self.0.minmax = Some((min, max));
+        self
+    }
+
+    pub fn export_mode(mut self, export_mode: ExportMode) -> Self {
+        self.0.export_mode = export_mode;
+        self
+    }
+
+    pub fn volume_access(mut self, volume_access: VolumeAccess) -> Self {
+        self.0.volume_access = volume_access;
+        self
+    }
+
+    pub fn memory_budget(mut self, memory_budget: MemoryBudget) -> Self {
+        self.0.memory_budget = Some(memory_budget);
+        self
+    }
+
+    pub fn on_error(mut self, on_error: OnError) -> Self {
+        self.0.on_error = on_error;
+        self
+    }
+
+    /// Skips the sources converted by the previous runs with the same settings, unless they
+    /// have changed since then. The completed sources are recorded in a manifest in `png_stub`.
+    ///
+    /// Needs [`OverwritePolicy::Overwrite`] for the volumes that weren't completed.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.0.resume = resume;
+        self
+    }
+
+    /// Writes a manifest of the exported images to `png_stub`: their sources, indices,
+    /// positions, sizes and intensity windows, along with the versions of the Python packages.
+    ///
+    /// With [`resume`](Self::resume), the images of the previous runs stay in the manifest,
+    /// unless their directories are exported again. Not supported by
+    /// [`convert_in_processes`](crate::process_pool::convert_in_processes).
+    pub fn output_manifest(mut self, format: ManifestFormat) -> Self {
+        self.0.output_manifest = Some(format);
+        self
+    }
+
+    /// What to do with the directories of the volumes that already exist.
+    /// [`OverwritePolicy::Error`] by default.
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.0.overwrite = policy;
+        self
+    }
+
+    /// What to do with the entries whose directories would share a name, such as `a.nii` and `a.nii.gz`
+    pub fn name_collision(mut self, policy: NameCollision) -> Self {
+        self.0.name_collision = policy;
+        self
+    }
+
+    /// Treats `nii_files` as a BIDS dataset: converts the volumes of its `sub-*` folders
+    /// that pass the filter and lays `png_stub` out as a derivatives dataset.
+    ///
+    /// The slices of `sub-01/anat/sub-01_T1w.nii.gz` are saved to `png_stub/sub-01/anat/sub-01_T1w/`
+    /// next to the sidecar `sub-01_T1w.json` with the metadata merged from the sidecars of the volume.
+    /// `png_stub/dataset_description.json` describes the derivatives.
+    /// The [`entries`](Self::entries) are paths relative to the root of the dataset.
+    pub fn bids(mut self, filter: BidsFilter) -> Self {
+        self.0.bids = Some(filter);
+        self
+    }
+
+    /// Saves the slices as NumPy arrays instead of the PNGs, oriented the same way and without
+    /// the 8-bit quantization unless [`ArrayDtype::Uint8`](crate::ArrayDtype::Uint8) is chosen.
+    /// The arrays are encoded in Rust on the calling thread, so [`ExportMode::Parallel`] has no effect.
+    pub fn array_export(mut self, arrays: ArrayExport) -> Self {
+        self.0.array_export = Some(arrays);
+        self
+    }
+
+    /// Writes the exported files, manifests included, to the sink instead of the directories.
+    ///
+    /// By default, the slices are saved to the directories by skimage and PIL, as the original code does.
+    /// The sinks get the same PNGs encoded in Rust, like the ones of [`ExportMode::Parallel`].
+    pub fn output_sink(mut self, sink: Arc<dyn OutputSink>) -> Self {
+        self.0.output_sink = Some(sink);
+        self
+    }
+
+    /// Names of the directories of the explicit [`entries`](Self::entries), which are otherwise
+    /// resolved from the listing of the whole directory
+    pub(crate) fn output_names(mut self, names: OutputNames) -> Self {
+        self.0.output_names = Some(names);
+        self
+    }
+
+    pub fn build(self) -> Result<ConvertOptions, ErrorTy> {
+        let options = self.0;
+        if options.nii_files.as_os_str().is_empty() {
+            return Err(EmptyPath("nii_files"));
+        }
+        if options.png_stub.as_os_str().is_empty() {
+            return Err(EmptyPath("png_stub"));
+        }
+        if let Some((min, max)) = options.minmax {
+            if min >= max {
+                return Err(InvalidMinmax(min, max));
+            }
+        }
+        if let Some(MemoryBudget { bytes: 0, .. }) = options.memory_budget {
+            return Err(ZeroMemoryBudget);
+        }
+        if options.resume
+            && options
+                .output_sink
+                .as_ref()
+                .is_some_and(|sink| !sink.is_local())
+        {
+            return Err(UnsupportedBySink("`resume`"));
+        }
+        if options.resume && options.overwrite != OverwritePolicy::Overwrite {
+            return Err(ResumeWithoutOverwrite(options.overwrite));
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_needs_overwrite() {
+        for policy in [
+            OverwritePolicy::Error,
+            OverwritePolicy::SkipExisting,
+            OverwritePolicy::Versioned,
+        ] {
+            let res = ConvertOptions::builder("nii")
+                .resume(true)
+                .overwrite(policy)
+                .build();
+            assert!(matches!(res, Err(ResumeWithoutOverwrite(p)) if p == policy));
+        }
+        let options = ConvertOptions::builder("nii")
+            .resume(true)
+            .overwrite(OverwritePolicy::Overwrite)
+            .build()
+            .unwrap();
+        assert_eq!(options.overwrite(), OverwritePolicy::Overwrite);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::error_ty::ErrorTy;
+
+/// Results of [`convert`](crate::convert)
+#[derive(Debug, Default)]
+pub struct ConvertReport {
+    /// Sources (files or DICOM series folders) that were converted successfully,
+    /// once per series of a DICOM folder
+    pub converted: Vec<PathBuf>,
+    /// Sources whose directories already existed with [`OverwritePolicy::SkipExisting`](crate::OverwritePolicy::SkipExisting)
+    /// or that were converted by a previous run with [`resume`](crate::ConvertOptionsBuilder::resume)
+    pub skipped: Vec<PathBuf>,
+    /// Sources that failed along with the reason, once per series of a DICOM folder.
+    /// Always empty with [`OnError::Stop`](crate::OnError::Stop).
+    pub failed: Vec<(PathBuf, ErrorTy)>,
+    // target directories of `converted`, in the same order
+    converted_dirs: Vec<PathBuf>,
+}
+
+impl ConvertReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Report whose converted sources have no known target directories,
+    /// e.g. of the [worker processes](crate::process_pool)
+    pub(crate) fn of_sources(
+        converted: Vec<PathBuf>,
+        skipped: Vec<PathBuf>,
+        failed: Vec<(PathBuf, ErrorTy)>,
+    ) -> Self {
+        Self {
+            converted,
+            skipped,
+            failed,
+            converted_dirs: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record_converted(&mut self, source: PathBuf, target_dir: PathBuf) {
+        self.converted.push(source);
+        self.converted_dirs.push(target_dir);
+    }
+
+    pub(crate) fn record_failure(&mut self, source: PathBuf, error: ErrorTy) {
+        self.failed.push((source, error));
+    }
+
+    /// Removes the volume converted to `target_dir` from `converted`, because some of its slices
+    /// failed to save. Returns `false` if it isn't there, e.g. when an earlier slice has failed.
+    pub(crate) fn take_converted(&mut self, target_dir: &Path) -> bool {
+        match self
+            .converted_dirs
+            .iter()
+            .position(|converted| converted == target_dir)
+        {
+            Some(i) => {
+                self.converted.remove(i);
+                self.converted_dirs.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for ConvertReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Converted: {}, skipped: {}, failed: {}",
+            self.converted.len(),
+            self.skipped.len(),
+            self.failed.len()
+        )?;
+        for (source, e) in self.failed.iter() {
+            write!(f, "\n\t{}: {e}", source.display())?;
+        }
+        Ok(())
+    }
+}
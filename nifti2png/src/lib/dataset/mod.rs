@@ -0,0 +1,442 @@
+//! Export of image volumes paired with their label volumes as a dataset for segmentation models,
+//! see [`export_dataset`]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use pyo3::prelude::*;
+use serde::Serialize;
+
+mod options;
+mod split;
+pub use options::{DatasetOptions, DatasetOptionsBuilder};
+pub use split::{Split, SplitRatios};
+
+use crate::{
+    bids::BidsEntities,
+    error_ty::ErrorTy::{self, *},
+    paths::{os_from_bytes, os_to_bytes},
+    ubyte_slice::UbyteSlice,
+    volume_format::VolumeFormat,
+    volumes::Volume,
+    DirectorySink, InputOrder, OnError, OutputSink,
+};
+
+/// Results of [`export_dataset`]
+#[derive(Debug, Default)]
+pub struct DatasetReport {
+    /// Images exported along with their labels and the split of their subject
+    pub exported: Vec<(PathBuf, Split)>,
+    /// Images without a label volume and label volumes without an image
+    pub unpaired: Vec<PathBuf>,
+    /// Number of slice pairs written to every split
+    pub slices: BTreeMap<Split, usize>,
+    /// Number of slice pairs left out because their masks were empty
+    pub skipped_empty: usize,
+    /// Images that failed along with the reason.
+    /// Always empty with [`OnError::Stop`].
+    pub failed: Vec<(PathBuf, ErrorTy)>,
+}
+
+impl DatasetReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for DatasetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Exported: {}", self.exported.len())?;
+        for (split, slices) in self.slices.iter() {
+            write!(f, ", {split} slices: {slices}")?;
+        }
+        write!(
+            f,
+            ", skipped empty: {}, unpaired: {}, failed: {}",
+            self.skipped_empty,
+            self.unpaired.len(),
+            self.failed.len()
+        )?;
+        for source in self.unpaired.iter() {
+            write!(f, "\n\tunpaired: {}", source.display())?;
+        }
+        for (source, e) in self.failed.iter() {
+            write!(f, "\n\t{}: {e}", source.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Image volume and its label volume, both entries of their directories
+struct Pair {
+    image: OsString,
+    label: OsString,
+    // The name of the image without the extension, shared by the exported slices
+    stem: OsString,
+    subject: String,
+}
+
+/// Row of `index.csv`
+#[derive(Serialize)]
+struct IndexRow {
+    split: &'static str,
+    subject: String,
+    image_source: String,
+    label_source: String,
+    t: usize,
+    z: usize,
+    image: String,
+    mask: String,
+    foreground_pixels: usize,
+}
+
+/// Exports the slices of the volumes of [`DatasetOptions::images`] along with the same slices
+/// of their label volumes in [`DatasetOptions::labels`]:
+///
+/// ```text
+/// <output>/
+///     index.csv
+///     train/images/<name>_<t>_<z>.png
+///     train/masks/<name>_<t>_<z>.png
+///     val/...
+///     test/...
+/// ```
+///
+/// The images are rescaled to grayscale like [`convert`](crate::convert) does. The masks keep the labels
+/// as the values of their single channel (clipped to 0-255) and have the same orientation as the images.
+/// The subjects are assigned to the splits as a whole, so the slices of a subject never leak
+/// from one split to another. The subject is the `sub` entity of BIDS names and the name
+/// of the volume otherwise.
+///
+/// Only volume files are paired, DICOM series are not supported.
+pub fn export_dataset(options: &DatasetOptions) -> Result<DatasetReport, ErrorTy> {
+    Python::with_gil(|py| {
+        let res = export_pairs(py, options);
+        if let Err(e) = &res {
+            tracing::error!(error = %e, "The dataset export failed");
+        }
+        res
+    })
+}
+
+fn export_pairs(py: Python<'_>, options: &DatasetOptions) -> Result<DatasetReport, ErrorTy> {
+    let sink: Arc<dyn OutputSink> = options
+        .output_sink
+        .clone()
+        .unwrap_or_else(|| Arc::new(DirectorySink));
+    let np = py.import("numpy").map_err(MissingThirdPartyLibrary)?;
+    let (exposure, img_as_ubyte) = {
+        let skimage = py.import("skimage").map_err(MissingThirdPartyLibrary)?;
+        (
+            skimage
+                .getattr("exposure")
+                .map_err(MissingComponentOfThirdPartyLibrary)?,
+            skimage
+                .getattr("img_as_ubyte")
+                .map_err(MissingComponentOfThirdPartyLibrary)?,
+        )
+    };
+
+    let mut report = DatasetReport::default();
+    let pairs = find_pairs(options, &mut report)?;
+    let splits = options.split.assign(
+        pairs.iter().map(|pair| pair.subject.clone()).collect(),
+        options.seed,
+    );
+
+    let mut index = csv::Writer::from_writer(Vec::new());
+    for pair in pairs {
+        // SAFETY: only the rows and the counts leave the iteration
+        let pair_gil_pool = unsafe { py.new_pool() };
+        let py = pair_gil_pool.python();
+
+        let source = options.images.join(&pair.image);
+        let split = splits[&pair.subject];
+        let _pair_span = tracing::info_span!("pair", source = %source.display(), %split).entered();
+        let exporter = PairExporter {
+            np,
+            exposure,
+            img_as_ubyte,
+            sink: &*sink,
+            options,
+        };
+        match exporter.export(py, &pair, split) {
+            Ok((rows, skipped_empty)) => {
+                tracing::info!(slices = rows.len(), skipped_empty, "Exported the pair");
+                for row in rows.iter() {
+                    index.serialize(row).map_err(|e| {
+                        ManifestWriteFailed(
+                            e.into(),
+                            options.output.join("index.csv").display().to_string(),
+                        )
+                    })?;
+                }
+                *report.slices.entry(split).or_default() += rows.len();
+                report.skipped_empty += skipped_empty;
+                report.exported.push((source, split));
+            }
+            Err(e) => record_failure(e.in_file(source), options.on_error, &mut report)?,
+        }
+    }
+
+    let index_path = options.output.join("index.csv");
+    let index = index
+        .into_inner()
+        .map_err(|e| ManifestWriteFailed(e.into_error(), index_path.display().to_string()))?;
+    sink.write(&index_path, &index)?;
+    sink.finish()?;
+    Ok(report)
+}
+
+/// Entries of the directory that are volume files, in the natural order, along with their names
+/// without the extension
+fn list_volumes(dir: &Path) -> Result<Vec<(OsString, OsString)>, ErrorTy> {
+    let read_dir_failed = |e| ReadDirFailed(e, dir.display().to_string());
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(read_dir_failed)? {
+        let entry = entry.map_err(read_dir_failed)?;
+        let name = entry.file_name();
+        // e.g. the temporary files of the atomic writes
+        if name.to_string_lossy().starts_with('.') || !entry.path().is_file() {
+            continue;
+        }
+        entries.push(name);
+    }
+    InputOrder::Natural.sort(dir, &mut entries);
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| Some((VolumeFormat::stem(&entry)?, entry)))
+        .collect())
+}
+
+/// Name of the image that the label volume belongs to, i.e. its name without `label_suffix`
+fn strip_label_suffix(label_stem: &OsStr, label_suffix: &str) -> Option<OsString> {
+    let bytes = os_to_bytes(label_stem);
+    let stripped = bytes.strip_suffix(label_suffix.as_bytes())?;
+    Some(os_from_bytes(stripped.to_vec()))
+}
+
+/// Pairs the images with their labels. The unpaired volumes are recorded in the report,
+/// the volumes that share their name with another one in the same directory are recorded as failed.
+fn find_pairs(options: &DatasetOptions, report: &mut DatasetReport) -> Result<Vec<Pair>, ErrorTy> {
+    // With a suffix, the labels may be stored along with the images
+    let same_dir = options.images == options.labels;
+
+    let mut labels: HashMap<OsString, Vec<OsString>> = HashMap::new();
+    for (stem, entry) in list_volumes(&options.labels)? {
+        match strip_label_suffix(&stem, &options.label_suffix) {
+            Some(image_stem) => labels.entry(image_stem).or_default().push(entry),
+            None if same_dir => (),
+            None => report.unpaired.push(options.labels.join(entry)),
+        }
+    }
+
+    let mut images: Vec<(OsString, Vec<OsString>)> = Vec::new();
+    for (stem, entry) in list_volumes(&options.images)? {
+        if same_dir && strip_label_suffix(&stem, &options.label_suffix).is_some() {
+            continue;
+        }
+        match images
+            .iter_mut()
+            .find(|(image_stem, _)| *image_stem == stem)
+        {
+            Some((_, entries)) => entries.push(entry),
+            None => images.push((stem, vec![entry])),
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (stem, mut image_entries) in images {
+        let label_entries = labels.remove(&stem);
+        if image_entries.len() > 1 {
+            let names = image_entries
+                .iter()
+                .map(|entry| entry.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ");
+            for entry in image_entries {
+                let e = AmbiguousPair(names.clone()).in_file(options.images.join(entry));
+                record_failure(e, options.on_error, report)?;
+            }
+            continue;
+        }
+        let image = image_entries.remove(0);
+        let label = match label_entries {
+            None => {
+                report.unpaired.push(options.images.join(image));
+                continue;
+            }
+            Some(mut label_entries) if label_entries.len() == 1 => label_entries.remove(0),
+            Some(label_entries) => {
+                let names = label_entries
+                    .iter()
+                    .map(|entry| entry.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let e = AmbiguousPair(names).in_file(options.images.join(image));
+                record_failure(e, options.on_error, report)?;
+                continue;
+            }
+        };
+        let subject = stem
+            .to_str()
+            .and_then(BidsEntities::parse)
+            .and_then(|entities| entities.get("sub").map(str::to_string))
+            .unwrap_or_else(|| stem.to_string_lossy().into_owned());
+        pairs.push(Pair {
+            image,
+            label,
+            stem,
+            subject,
+        });
+    }
+    // The labels left have no images
+    let mut unpaired_labels = labels.into_values().flatten().collect::<Vec<_>>();
+    InputOrder::Natural.sort(&options.labels, &mut unpaired_labels);
+    report.unpaired.extend(
+        unpaired_labels
+            .into_iter()
+            .map(|entry| options.labels.join(entry)),
+    );
+    Ok(pairs)
+}
+
+/// Python modules and settings shared by the pairs
+struct PairExporter<'py, 'a> {
+    np: &'py PyModule,
+    exposure: &'py PyAny,
+    img_as_ubyte: &'py PyAny,
+    sink: &'a dyn OutputSink,
+    options: &'a DatasetOptions,
+}
+
+impl<'py, 'a> PairExporter<'py, 'a> {
+    /// Exports the slices of the pair. Returns the rows of the index and the number of the empty masks left out.
+    fn export(
+        &self,
+        py: Python<'py>,
+        pair: &Pair,
+        split: Split,
+    ) -> Result<(Vec<IndexRow>, usize), ErrorTy> {
+        let options = self.options;
+        let image = Volume::open(py, &options.images, &pair.image)?;
+        let label = Volume::open(py, &options.labels, &pair.label)?;
+        let image_source = image.source().display().to_string();
+        let label_source = label.source().display().to_string();
+
+        let (image_shape, label_shape) = (&image.header().shape, &label.header().shape);
+        let [z_count, t_count] = image.slice_counts();
+        let [label_z_count, label_t_count] = label.slice_counts();
+        // A 3D label volume applies to every volume of a 4D image
+        if image_shape[..3] != label_shape[..3]
+            || z_count != label_z_count
+            || (label_t_count != 1 && label_t_count != t_count)
+        {
+            return Err(LabelShapeMismatch(
+                label_source,
+                label_shape.clone(),
+                image_shape.clone(),
+            ));
+        }
+
+        let image = image.into_image().rescale_intensity_to_unit_interval(
+            py,
+            self.exposure,
+            options.minmax,
+        )?;
+        let split_dir = options.output.join(split.name());
+        let mut rows = Vec::new();
+        let mut skipped_empty = 0;
+        for t in 0..t_count {
+            for z in 0..z_count {
+                // SAFETY: only the copied slices leave the iteration
+                let slice_gil_pool = unsafe { py.new_pool() };
+                let py = slice_gil_pool.python();
+
+                let mask = self.mask_slice(py, &label, [z, t.min(label_t_count - 1)])?;
+                let foreground_pixels = mask
+                    .to_luma_image()
+                    .pixels()
+                    .filter(|pixel| pixel.0[0] != 0)
+                    .count();
+                if foreground_pixels == 0 && options.skip_empty_masks {
+                    skipped_empty += 1;
+                    continue;
+                }
+
+                let mut file_name = pair.stem.clone();
+                file_name.push(format!("_{t:04}_{z:04}.png"));
+                let image_path = split_dir.join("images").join(&file_name);
+                let mask_path = split_dir.join("masks").join(&file_name);
+                image
+                    .get_slice(py, [z as isize, t as isize])?
+                    .to_ubyte(self.img_as_ubyte)?
+                    .save(self.sink, &image_path)?;
+                mask.save_luma(self.sink, &mask_path)?;
+
+                let rel = |path: &Path| {
+                    path.strip_prefix(&options.output)
+                        .unwrap_or(path)
+                        .display()
+                        .to_string()
+                };
+                rows.push(IndexRow {
+                    split: split.name(),
+                    subject: pair.subject.clone(),
+                    image_source: image_source.clone(),
+                    label_source: label_source.clone(),
+                    t,
+                    z,
+                    image: rel(&image_path),
+                    mask: rel(&mask_path),
+                    foreground_pixels,
+                });
+            }
+        }
+        Ok((rows, skipped_empty))
+    }
+
+    /// Slice of the label volume with the labels rounded to `uint8`
+    fn mask_slice(
+        &self,
+        py: Python<'py>,
+        label: &Volume<'py>,
+        index: [usize; 2],
+    ) -> Result<UbyteSlice, ErrorTy> {
+        let slice = label.slice(py, index)?;
+        let shape = slice.getattr("shape")?.extract::<(usize, usize)>()?;
+        let labels = self
+            .np
+            .call_method1("rint", (slice,))?
+            .call_method1("clip", (0, 255))?
+            .call_method1("astype", ("uint8",))?;
+        // tobytes() returns the C-ordered data even for non-contiguous views
+        let bytes = labels.call_method0("tobytes")?;
+        Ok(UbyteSlice {
+            voxels: bytes.extract::<&[u8]>()?.to_vec(),
+            dims: [shape.0, shape.1],
+        })
+    }
+}
+
+/// Records the failure of a single image in the report with [`OnError::Continue`].
+/// Returns the error otherwise or if it doesn't belong to a single image.
+fn record_failure(
+    e: ErrorTy,
+    on_error: OnError,
+    report: &mut DatasetReport,
+) -> Result<(), ErrorTy> {
+    match (e, on_error) {
+        (FileFailed(source, e), OnError::Continue) => {
+            tracing::warn!(source = %source.display(), error = %e, "Failed to export the pair");
+            report.failed.push((source, *e));
+            Ok(())
+        }
+        (e, _) => Err(e),
+    }
+}
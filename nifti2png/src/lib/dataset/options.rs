@@ -0,0 +1,142 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::SplitRatios;
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    OnError, OutputSink,
+};
+
+/// Settings of [`export_dataset`](crate::export_dataset), created with [`DatasetOptions::builder`]
+#[derive(Clone, Debug)]
+pub struct DatasetOptions {
+    pub(crate) images: PathBuf,
+    pub(crate) labels: PathBuf,
+    pub(crate) output: PathBuf,
+    pub(crate) label_suffix: String,
+    pub(crate) minmax: Option<(u64, u64)>,
+    pub(crate) skip_empty_masks: bool,
+    pub(crate) split: SplitRatios,
+    pub(crate) seed: u64,
+    pub(crate) on_error: OnError,
+    pub(crate) output_sink: Option<Arc<dyn OutputSink>>,
+}
+
+/// Builder of [`DatasetOptions`]
+#[derive(Clone, Debug)]
+pub struct DatasetOptionsBuilder(DatasetOptions);
+
+impl DatasetOptions {
+    /// Starts building the options for exporting the volumes of the `images` directory
+    /// paired with the label volumes of the same names in the `labels` directory to `output`
+    pub fn builder(
+        images: impl AsRef<Path>,
+        labels: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+    ) -> DatasetOptionsBuilder {
+        DatasetOptionsBuilder(DatasetOptions {
+            images: images.as_ref().to_path_buf(),
+            labels: labels.as_ref().to_path_buf(),
+            output: output.as_ref().to_path_buf(),
+            label_suffix: String::new(),
+            minmax: None,
+            skip_empty_masks: false,
+            split: SplitRatios::default(),
+            seed: 0,
+            on_error: OnError::default(),
+            output_sink: None,
+        })
+    }
+
+    pub fn images(&self) -> &Path {
+        &self.images
+    }
+
+    pub fn labels(&self) -> &Path {
+        &self.labels
+    }
+
+    pub fn output(&self) -> &Path {
+        &self.output
+    }
+
+    pub fn split(&self) -> SplitRatios {
+        self.split
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl DatasetOptionsBuilder {
+    /// Suffix that the names of the label volumes have in addition to the names of the images,
+    /// e.g. `_mask` pairs `lung_01.nii.gz` with `lung_01_mask.nii.gz`. Empty by default.
+    pub fn label_suffix(mut self, label_suffix: impl Into<String>) -> Self {
+        self.0.label_suffix = label_suffix.into();
+        self
+    }
+
+    /// Intensities of the images mapped to black and white. The range of every image by default.
+    pub fn minmax(mut self, min: u64, max: u64) -> Self {
+        self.0.minmax = Some((min, max));
+        self
+    }
+
+    /// Leaves out the slices whose masks have no labelled voxels
+    pub fn skip_empty_masks(mut self, skip_empty_masks: bool) -> Self {
+        self.0.skip_empty_masks = skip_empty_masks;
+        self
+    }
+
+    /// Relative sizes of the train, validation and test splits. 80/10/10 by default.
+    pub fn split(mut self, split: SplitRatios) -> Self {
+        self.0.split = split;
+        self
+    }
+
+    /// Seed of the shuffle of the subjects before they are split. 0 by default.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.0.seed = seed;
+        self
+    }
+
+    pub fn on_error(mut self, on_error: OnError) -> Self {
+        self.0.on_error = on_error;
+        self
+    }
+
+    /// Writes the exported files to the sink instead of the directories,
+    /// see [`ConvertOptionsBuilder::output_sink`](crate::ConvertOptionsBuilder::output_sink)
+    pub fn output_sink(mut self, sink: Arc<dyn OutputSink>) -> Self {
+        self.0.output_sink = Some(sink);
+        self
+    }
+
+    pub fn build(self) -> Result<DatasetOptions, ErrorTy> {
+        let options = self.0;
+        if options.images.as_os_str().is_empty() {
+            return Err(EmptyPath("images"));
+        }
+        if options.labels.as_os_str().is_empty() {
+            return Err(EmptyPath("labels"));
+        }
+        if options.output.as_os_str().is_empty() {
+            return Err(EmptyPath("output"));
+        }
+        if options.images == options.labels && options.label_suffix.is_empty() {
+            return Err(EmptyLabelSuffix);
+        }
+        if let Some((min, max)) = options.minmax {
+            if min >= max {
+                return Err(InvalidMinmax(min, max));
+            }
+        }
+        if !options.split.is_valid() {
+            return Err(InvalidSplitRatios(options.split));
+        }
+        Ok(options)
+    }
+}
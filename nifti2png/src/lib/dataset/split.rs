@@ -0,0 +1,149 @@
+use std::{collections::HashMap, fmt};
+
+/// Part of the dataset a subject is assigned to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Split {
+    Train,
+    Val,
+    Test,
+}
+
+impl Split {
+    /// Name of the directory of the split and its value in the index
+    pub fn name(self) -> &'static str {
+        match self {
+            Split::Train => "train",
+            Split::Val => "val",
+            Split::Test => "test",
+        }
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Relative sizes of the splits, measured in subjects. They don't have to add up to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplitRatios {
+    pub train: f64,
+    pub val: f64,
+    pub test: f64,
+}
+
+impl Default for SplitRatios {
+    fn default() -> Self {
+        Self {
+            train: 0.8,
+            val: 0.1,
+            test: 0.1,
+        }
+    }
+}
+
+impl SplitRatios {
+    pub(crate) fn is_valid(&self) -> bool {
+        let ratios = [self.train, self.val, self.test];
+        ratios
+            .iter()
+            .all(|ratio| ratio.is_finite() && *ratio >= 0.0)
+            && ratios.iter().sum::<f64>() > 0.0
+    }
+
+    /// Assigns the subjects to the splits. The subjects are shuffled with the seed,
+    /// so the assignment only depends on the set of subjects, the ratios and the seed.
+    pub(crate) fn assign(&self, mut subjects: Vec<String>, seed: u64) -> HashMap<String, Split> {
+        // The order of discovery doesn't matter
+        subjects.sort();
+        subjects.dedup();
+        let mut rng = SplitMix64(seed);
+        // Fisher-Yates
+        for i in (1..subjects.len()).rev() {
+            let j = (rng.next() % (i as u64 + 1)) as usize;
+            subjects.swap(i, j);
+        }
+
+        let total = self.train + self.val + self.test;
+        let n = subjects.len();
+        let train = ((n as f64 * self.train / total).round() as usize).min(n);
+        let val = ((n as f64 * self.val / total).round() as usize).min(n - train);
+        subjects
+            .into_iter()
+            .enumerate()
+            .map(|(i, subject)| {
+                let split = match i {
+                    i if i < train => Split::Train,
+                    i if i < train + val => Split::Val,
+                    _ => Split::Test,
+                };
+                (subject, split)
+            })
+            .collect()
+    }
+}
+
+/// https://prng.di.unimi.it/splitmix64.c, small and stable across versions unlike the `rand` crates
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subjects(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("{i:02}")).collect()
+    }
+
+    /// Number of subjects in train, val and test
+    fn counts(assignment: &HashMap<String, Split>) -> [usize; 3] {
+        let count = |split| assignment.values().filter(|&&s| s == split).count();
+        [count(Split::Train), count(Split::Val), count(Split::Test)]
+    }
+
+    #[test]
+    fn splitmix64_is_stable() {
+        // the first output of the reference implementation seeded with 0
+        assert_eq!(SplitMix64(0).next(), 0xe220a8397b1dcdaf);
+    }
+
+    #[test]
+    fn assignment_depends_on_the_set_and_the_seed() {
+        let ratios = SplitRatios::default();
+        let assignment = ratios.assign(subjects(20), 7);
+        assert_eq!(assignment.len(), 20);
+        assert_eq!(counts(&assignment), [16, 2, 2]);
+
+        let mut shuffled = subjects(20);
+        shuffled.reverse();
+        shuffled.push("05".to_string());
+        assert_eq!(ratios.assign(shuffled, 7), assignment);
+        assert!((0..10).any(|seed| ratios.assign(subjects(20), seed) != assignment));
+    }
+
+    #[test]
+    fn rounding_with_few_subjects() {
+        let ratios = SplitRatios::default();
+        assert_eq!(counts(&ratios.assign(Vec::new(), 0)), [0, 0, 0]);
+        assert_eq!(counts(&ratios.assign(subjects(1), 0)), [1, 0, 0]);
+        assert_eq!(counts(&ratios.assign(subjects(3), 0)), [2, 0, 1]);
+        let thirds = SplitRatios {
+            train: 1.0,
+            val: 1.0,
+            test: 1.0,
+        };
+        // val gets what's left after train, test the rest
+        assert_eq!(counts(&thirds.assign(subjects(2), 0)), [1, 1, 0]);
+        assert_eq!(counts(&thirds.assign(subjects(4), 0)), [1, 1, 2]);
+    }
+}
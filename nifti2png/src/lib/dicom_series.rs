@@ -0,0 +1,348 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use pyo3::{
+    exceptions::{PyIndexError, PyValueError},
+    prelude::*,
+    types::{PyDict, PyList, PyTuple},
+};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    memory_budget::{estimate_memory, BudgetDecision, MemoryBudget},
+    paths::py_path,
+    volume_access::{FloatDtype, VolumeAccess},
+};
+
+/// DICOM files that share the same `SeriesInstanceUID`
+///
+/// Holds only the paths to the files, which are read once the series is assembled.
+pub(crate) struct DicomSeries {
+    pub(crate) uid: String,
+    // paths in no particular order
+    paths: Vec<PyObject>,
+    // the directory where the series was found (used in error messages)
+    dir: PathBuf,
+}
+
+fn floats(value: &PyAny) -> PyResult<Vec<f64>> {
+    value.iter()?.map(|v| v?.extract::<f64>()).collect()
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+/// Exactly `N` numbers of a multi-valued attribute
+fn array<const N: usize>(value: &PyAny) -> PyResult<[f64; N]> {
+    floats(value)?.try_into().map_err(|v: Vec<f64>| {
+        PyValueError::new_err(format!("expected {N} values, found {}", v.len()))
+    })
+}
+
+/// Walks `dir` recursively and groups all DICOM files found there by `SeriesInstanceUID`.
+///
+/// Files that pydicom doesn't recognize as DICOM are ignored, just like the DICOM files
+/// without images, e.g. `DICOMDIR`s and structured reports, which have no `SeriesInstanceUID`.
+pub(crate) fn find_dicom_series(
+    py: Python,
+    os: &PyModule,
+    dir: &Path,
+) -> Result<Vec<DicomSeries>, ErrorTy> {
+    let pydicom = py.import("pydicom").map_err(MissingThirdPartyLibrary)?;
+    let invalid_dicom_error = pydicom
+        .getattr("errors")
+        .and_then(|errors| errors.getattr("InvalidDicomError"))
+        .map_err(MissingComponentOfThirdPartyLibrary)?;
+
+    let mut series = BTreeMap::<String, Vec<PyObject>>::new();
+    for entry in os.call_method1("walk", (py_path(py, dir)?,))?.iter()? {
+        let (root, dirs, files): (&PyAny, &PyList, &PyList) = entry?.extract()?;
+        // os.walk follows the sorted `dirs`, so the first file of every series is the same on all machines
+        dirs.sort()?;
+        files.sort()?;
+        for file in files {
+            let path = os.getattr("path")?.call_method1("join", (root, file))?;
+            let ds = match pydicom.call_method(
+                "dcmread",
+                (path,),
+                Some({
+                    let kwargs = PyDict::new(py);
+                    kwargs.set_item("stop_before_pixels", true)?;
+                    kwargs
+                }),
+            ) {
+                Ok(ds) => ds,
+                Err(e) if e.is_instance(py, invalid_dicom_error) => continue,
+                Err(e) => return Err(ReadDicomFailed(e, path.str()?.to_string())),
+            };
+            let uid = match ds.getattr("SeriesInstanceUID") {
+                Ok(uid) => uid.str()?.to_string(),
+                Err(_) => {
+                    tracing::warn!(path = %path.str()?, "Skipping the DICOM file without SeriesInstanceUID");
+                    continue;
+                }
+            };
+            series.entry(uid).or_default().push(path.into());
+        }
+    }
+
+    Ok(series
+        .into_iter()
+        .map(|(uid, paths)| DicomSeries {
+            uid,
+            paths,
+            dir: dir.to_path_buf(),
+        })
+        .collect())
+}
+
+/// Slices of a DICOM series, read from the files on demand.
+///
+/// Sliced like nibabel's array proxy, so it stands in for the data of the assembled volume
+/// when the whole volume doesn't fit into the memory budget.
+#[pyclass]
+struct DicomSlices {
+    // (path, RescaleSlope, RescaleIntercept) sorted along the slice normal
+    files: Vec<(PyObject, f64, f64)>,
+    // [columns, rows]
+    size: [usize; 2],
+}
+
+impl DicomSlices {
+    /// Rescaled pixels of the slice, indexed as `[column, row]`
+    fn plane<'py>(&self, py: Python<'py>, z: usize) -> PyResult<&'py PyAny> {
+        let (path, slope, intercept) = &self.files[z];
+        let pixels = py
+            .import("pydicom")?
+            .call_method1("dcmread", (path,))?
+            .getattr("pixel_array")?
+            .call_method1("astype", ("float64",))?
+            .call_method1("__mul__", (*slope,))?
+            .call_method1("__add__", (*intercept,))?;
+        // (rows, columns) -> (columns, rows)
+        pixels.getattr("T")
+    }
+
+    /// Stacks all slices into a float64 ndarray
+    fn stack<'py>(&self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        let planes = (0..self.files.len())
+            .map(|z| self.plane(py, z))
+            .collect::<PyResult<Vec<_>>>()?;
+        py.import("numpy")?.call_method(
+            "stack",
+            (planes,),
+            Some({
+                let kwargs = PyDict::new(py);
+                kwargs.set_item("axis", -1)?;
+                kwargs
+            }),
+        )
+    }
+}
+
+#[pymethods]
+impl DicomSlices {
+    #[getter]
+    fn shape(&self) -> (usize, usize, usize) {
+        (self.size[0], self.size[1], self.files.len())
+    }
+
+    #[getter]
+    fn ndim(&self) -> usize {
+        3
+    }
+
+    #[getter]
+    fn dtype<'py>(&self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        py.import("numpy")?.call_method1("dtype", ("float64",))
+    }
+
+    #[getter]
+    fn is_proxy(&self) -> bool {
+        true
+    }
+
+    /// Reads only the slice for `[i, j, z]` with an integer `z`, the whole volume otherwise
+    fn __getitem__<'py>(&self, py: Python<'py>, index: &'py PyAny) -> PyResult<&'py PyAny> {
+        let z = index
+            .downcast::<PyTuple>()
+            .ok()
+            .filter(|index| index.len() == 3)
+            .and_then(|index| Some((index, index.get_item(2).ok()?.extract::<isize>().ok()?)));
+        let Some((index, z)) = z else {
+            return self.stack(py)?.get_item(index);
+        };
+        let count = self.files.len() as isize;
+        let z = if z < 0 { z + count } else { z };
+        if !(0..count).contains(&z) {
+            return Err(PyIndexError::new_err(format!("slice {z} is out of bounds")));
+        }
+        self.plane(py, z as usize)?
+            .get_item((index.get_item(0)?, index.get_item(1)?))
+    }
+
+    fn __array__<'py>(&self, py: Python<'py>) -> PyResult<&'py PyAny> {
+        self.stack(py)
+    }
+}
+
+impl DicomSeries {
+    /// Sorts the slices by their position along the slice normal and assembles them into
+    /// a `nibabel.Nifti1Image` with the RAS+ affine derived from the DICOM geometry.
+    ///
+    /// The data is indexed as `[column, row, slice]`, just like volumes converted with
+    /// the common DICOM to NIFTI converters.
+    ///
+    /// The memory needed to stack the slices is estimated from the headers before any pixels are read.
+    /// With [`OverBudget::Stream`](crate::OverBudget::Stream), the series that don't fit into the budget
+    /// are read slice by slice.
+    pub(crate) fn into_nii_obj<'a>(
+        self,
+        py: Python<'a>,
+        nib: &'a PyModule,
+        memory_budget: Option<MemoryBudget>,
+    ) -> Result<&'a PyAny, ErrorTy> {
+        let np = py.import("numpy").map_err(MissingThirdPartyLibrary)?;
+        let pydicom = py.import("pydicom").map_err(MissingThirdPartyLibrary)?;
+        let invalid = |e: PyErr| InvalidDicomSeries(e, self.dir.display().to_string());
+
+        let mut datasets = Vec::with_capacity(self.paths.len());
+        for path in self.paths.iter() {
+            let ds = pydicom
+                .call_method(
+                    "dcmread",
+                    (path,),
+                    Some({
+                        let kwargs = PyDict::new(py);
+                        kwargs.set_item("stop_before_pixels", true)?;
+                        kwargs
+                    }),
+                )
+                .map_err(|e| ReadDicomFailed(e, path.to_string()))?;
+            datasets.push((path, ds));
+        }
+
+        let (_, first) = datasets[0];
+        let iop: [f64; 6] = first
+            .getattr("ImageOrientationPatient")
+            .and_then(array)
+            .map_err(invalid)?;
+        let row_cos = [iop[0], iop[1], iop[2]];
+        let col_cos = [iop[3], iop[4], iop[5]];
+        let normal = cross(row_cos, col_cos);
+        // [row spacing, column spacing]
+        let pixel_spacing: [f64; 2] = first
+            .getattr("PixelSpacing")
+            .and_then(array)
+            .map_err(invalid)?;
+        let rows = first.getattr("Rows").map_err(invalid)?.extract::<usize>()?;
+        let columns = first
+            .getattr("Columns")
+            .map_err(invalid)?
+            .extract::<usize>()?;
+
+        let mut slices = Vec::with_capacity(datasets.len());
+        for (path, ds) in datasets.iter() {
+            let ipp = ds
+                .getattr("ImagePositionPatient")
+                .and_then(array)
+                .map_err(invalid)?;
+            slices.push((dot(ipp, normal), ipp, *path, *ds));
+        }
+        slices.sort_by(|(a, ..), (b, ..)| a.total_cmp(b));
+
+        let (_, origin, ..) = slices[0];
+        let slice_step: [f64; 3] = if slices.len() > 1 {
+            let (_, last, ..) = slices[slices.len() - 1];
+            let n = (slices.len() - 1) as f64;
+            std::array::from_fn(|i| (last[i] - origin[i]) / n)
+        } else {
+            let thickness = first
+                .getattr("SliceThickness")
+                .and_then(|t| t.extract::<f64>())
+                .unwrap_or(1.0);
+            normal.map(|n| n * thickness)
+        };
+
+        let mut files = Vec::with_capacity(slices.len());
+        for (_, _, path, ds) in slices.iter() {
+            let slope = ds
+                .getattr("RescaleSlope")
+                .and_then(|v| v.extract::<f64>())
+                .unwrap_or(1.0);
+            let intercept = ds
+                .getattr("RescaleIntercept")
+                .and_then(|v| v.extract::<f64>())
+                .unwrap_or(0.0);
+            files.push(((*path).clone_ref(py), slope, intercept));
+        }
+        let dims = [columns as isize, rows as isize, files.len() as isize, 1];
+        let slices = DicomSlices {
+            files,
+            size: [columns, rows],
+        };
+
+        // Stacking needs the float64 slices and their stack at once
+        let stacked = VolumeAccess::Full(FloatDtype::Float64);
+        let access = match memory_budget.map(|budget| (budget, budget.check(&dims, 8, stacked))) {
+            None => stacked,
+            Some((_, BudgetDecision::Accept(access))) => access,
+            Some((budget, BudgetDecision::Reject)) => {
+                let estimate = estimate_memory(&dims, 8, stacked);
+                const MIB: u64 = 1 << 20;
+                tracing::warn!(
+                    source = %self.dir.display(),
+                    series = %self.uid,
+                    estimate_mib = estimate / MIB,
+                    budget_mib = budget.bytes / MIB,
+                    "Estimated memory of the DICOM series exceeds the budget"
+                );
+                return Err(OverMemoryBudget(
+                    self.dir.display().to_string(),
+                    estimate,
+                    budget.bytes,
+                ));
+            }
+        };
+        let data = match access {
+            VolumeAccess::Full(_) | VolumeAccess::Auto => slices.stack(py).map_err(invalid)?,
+            VolumeAccess::Lazy => {
+                tracing::info!(
+                    source = %self.dir.display(),
+                    series = %self.uid,
+                    "Reading the DICOM series slice by slice to stay within the memory budget"
+                );
+                Py::new(py, slices)?.into_ref(py)
+            }
+        };
+
+        // LPS+ (DICOM) -> RAS+ (NIFTI)
+        let lps2ras = [-1.0, -1.0, 1.0];
+        let affine: Vec<[f64; 4]> = (0..3)
+            .map(|i| {
+                [
+                    lps2ras[i] * row_cos[i] * pixel_spacing[1],
+                    lps2ras[i] * col_cos[i] * pixel_spacing[0],
+                    lps2ras[i] * slice_step[i],
+                    lps2ras[i] * origin[i],
+                ]
+            })
+            .chain(std::iter::once([0.0, 0.0, 0.0, 1.0]))
+            .collect();
+        let affine = np.call_method1("array", (affine,))?;
+
+        let nii_obj = nib.getattr("Nifti1Image")?.call1((data, affine))?;
+        Ok(nii_obj)
+    }
+}
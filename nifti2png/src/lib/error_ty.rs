@@ -1,7 +1,10 @@
+use std::path::PathBuf;
+
 use pyo3::PyErr;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ErrorTy {
     #[error("Missing standard Python library: {0}")]
     MissingStandardLibrary(PyErr),
@@ -15,10 +18,108 @@ pub enum ErrorTy {
     CreateDirAllFailed(std::io::Error, String),
     #[error("std::path::Path::try_exists({1}) failed: {0}")]
     TryExistsFailed(std::io::Error, String),
+    #[error("std::fs::read_dir({1}) failed: {0}")]
+    ReadDirFailed(std::io::Error, String),
+    #[error("std::fs::remove_dir_all({1}) failed: {0}")]
+    RemoveDirAllFailed(std::io::Error, String),
+    #[error("std::fs::remove_file({1}) failed: {0}")]
+    RemoveFileFailed(std::io::Error, String),
+    #[error("std::fs::File::create({1}) failed: {0}")]
+    CreateFileFailed(std::io::Error, String),
+    #[error("std::fs::rename({1}) failed: {0}")]
+    RenameFailed(std::io::Error, String),
+    #[error("Writing {1} failed: {0}")]
+    SinkWriteFailed(std::io::Error, String),
+    #[error("{0} is only supported by the sinks writing to local directories")]
+    UnsupportedBySink(&'static str),
+    #[error("{0} already exists and is not a directory")]
+    TargetNotADirectory(String),
+    #[error("{0} already exists and is not empty, see `OverwritePolicy`")]
+    TargetExists(String),
+    #[error("{0} share the name of the output directory, see `NameCollision`")]
+    OutputNameCollision(String),
     #[error("image::open({1}) failed: {0}")]
     ImageOpenFailed(image::ImageError, String),
-    #[error("std::env::temp_dir() returned a non-UTF-8 path")]
-    TempDirNotUtf8,
+    #[error("The NIFTI image {1} has an unsupported dimensionality: {0} (expected 3 or 4)")]
+    UnsupportedDimensionality(usize, String),
+    #[error("The dimensions of {0} are {1:?} while {2:?} were expected")]
+    PngDimensionsMismatch(String, (u32, u32), (u32, u32)),
+    #[error("pydicom.dcmread({1}) failed: {0}")]
+    ReadDicomFailed(PyErr, String),
+    #[error("Invalid DICOM series in {1}: {0}")]
+    InvalidDicomSeries(PyErr, String),
+    #[error("image::save({1}) failed: {0}")]
+    ImageSaveFailed(image::ImageError, String),
+    #[error("A slice encoder thread panicked")]
+    EncoderThreadPanicked,
+    #[error("All slice encoder threads have stopped")]
+    EncoderThreadsStopped,
+    #[error("Failed to start a worker process: {0}")]
+    SpawnWorkerFailed(std::io::Error),
+    #[error("Invalid job header of the worker process: {0}")]
+    InvalidJobHeader(serde_json::Error),
+    #[error("Failed to read the job of the worker process: {0}")]
+    ReadJobFailed(std::io::Error),
+    /// Failure of an entry as reported by a [worker process](crate::process_pool)
+    #[error("{0}")]
+    WorkerFailed(String),
+    #[error("Converting {0} needs about {1} bytes, which exceeds the memory budget of {2} bytes")]
+    OverMemoryBudget(String, u64, u64),
+    #[error("`{0}` must not be empty")]
+    EmptyPath(&'static str),
+    #[error("Invalid `minmax`: {0} must be less than {1}")]
+    InvalidMinmax(u64, u64),
+    #[error("Invalid split ratios {0:?}: they must be finite, non-negative and not all zero")]
+    InvalidSplitRatios(crate::SplitRatios),
+    #[error("`label_suffix` must not be empty when the labels are stored along with the images")]
+    EmptyLabelSuffix,
+    #[error("{0} is neither a volume of a supported format nor a folder with a DICOM series")]
+    NoVolume(String),
+    #[error("{0} contains {1} DICOM series, so the volume is ambiguous")]
+    MultipleDicomSeries(String, usize),
+    #[error("{0} share the same name, so they can't be paired unambiguously")]
+    AmbiguousPair(String),
+    #[error("The label volume {0} has the shape {1:?} while the image has {2:?}")]
+    LabelShapeMismatch(String, Vec<usize>, Vec<usize>),
+    #[error("The memory budget must not be zero")]
+    ZeroMemoryBudget,
+    #[error("Failed to set up logging: {0}")]
+    LoggingSetupFailed(String),
+    #[error("The slice {0:?} is out of bounds, the volume has {1:?} slices")]
+    SliceOutOfBounds([usize; 2], [usize; 2]),
+    #[error("Failed to read the manifest {1}: {0}")]
+    ManifestReadFailed(std::io::Error, String),
+    #[error("Failed to write the manifest {1}: {0}")]
+    ManifestWriteFailed(std::io::Error, String),
+    #[error("Failed to read the sidecar {1}: {0}")]
+    SidecarReadFailed(std::io::Error, String),
+    #[error("Failed to read the categories {1}: {0}")]
+    CategoriesReadFailed(std::io::Error, String),
+    #[error("At least one category must be given")]
+    NoCategories,
+    #[error("`resume` overwrites the volumes that weren't completed, which conflicts with {0:?}")]
+    ResumeWithoutOverwrite(crate::OverwritePolicy),
+    #[error("{0} is not supported with worker processes")]
+    UnsupportedInProcesses(&'static str),
+    /// Failure of a single file (or DICOM series folder), see [`ErrorTy::root_cause`]
+    #[error("{}: {1}", .0.display())]
+    FileFailed(PathBuf, Box<ErrorTy>),
     #[error("{0}")]
     UncategorizedPyErr(#[from] PyErr),
 }
+
+impl ErrorTy {
+    /// Attributes the error to the file (or the DICOM series folder) that failed to convert
+    pub(crate) fn in_file(self, source: impl Into<PathBuf>) -> Self {
+        ErrorTy::FileFailed(source.into(), Box::new(self))
+    }
+
+    /// The error without the file it is attributed to, e.g. to match
+    /// [`ErrorTy::OverMemoryBudget`] whether it is wrapped in [`ErrorTy::FileFailed`] or not
+    pub fn root_cause(&self) -> &ErrorTy {
+        match self {
+            ErrorTy::FileFailed(_, e) => e.root_cause(),
+            e => e,
+        }
+    }
+}
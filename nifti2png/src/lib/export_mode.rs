@@ -0,0 +1,15 @@
+use std::num::NonZeroUsize;
+
+use serde::{Deserialize, Serialize};
+
+/// How [`convert`](crate::convert) encodes and writes the slices
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportMode {
+    /// Every slice is encoded with skimage and PIL on the calling thread, just like in the original Python code.
+    #[default]
+    Sequential,
+    /// Slices are extracted with the GIL held and then oriented, encoded and written
+    /// by the given number of Rust threads with the GIL released.
+    /// Of a 4D volume, only the slices of the last 3D volume are encoded, since they overwrite the others.
+    Parallel(NonZeroUsize),
+}
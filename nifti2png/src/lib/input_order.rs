@@ -0,0 +1,147 @@
+use std::{
+    cmp::Ordering,
+    ffi::{OsStr, OsString},
+    path::Path,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Order in which [`convert`](crate::convert) processes the entries of `nii_files`.
+///
+/// `os.listdir` lists the entries in the order of the filesystem, which differs between machines,
+/// so they are always sorted. Explicitly given [`entries`](crate::ConvertOptionsBuilder::entries)
+/// keep their order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputOrder {
+    /// By name with the runs of digits compared as numbers, so `sub-2` comes before `sub-10`
+    #[default]
+    Natural,
+    /// From the least to the most recently modified, the ties and the entries
+    /// whose modification time can't be read (they come first) in the natural order
+    ModifiedTime,
+}
+
+impl InputOrder {
+    /// Sorts the entries of the `nii_files` directory
+    pub(crate) fn sort(self, nii_files: &Path, entries: &mut [OsString]) {
+        match self {
+            InputOrder::Natural => entries.sort_by(|a, b| natural_cmp(a, b)),
+            InputOrder::ModifiedTime => entries.sort_by_cached_key(|entry| {
+                let mtime: Option<SystemTime> = std::fs::metadata(nii_files.join(entry))
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                (mtime, NaturalKey(entry.clone()))
+            }),
+        }
+    }
+}
+
+/// [`OsString`] ordered by [`natural_cmp`]
+#[derive(PartialEq, Eq)]
+struct NaturalKey(OsString);
+
+impl PartialOrd for NaturalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NaturalKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        natural_cmp(&self.0, &other.0)
+    }
+}
+
+/// Compares the names chunk by chunk, where the runs of ASCII digits are compared as numbers
+/// and the rest character by character. Names that only differ in the leading zeros
+/// or in the characters that aren't UTF-8 are ordered by their raw representation.
+fn natural_cmp(a: &OsStr, b: &OsStr) -> Ordering {
+    let (a_lossy, b_lossy) = (a.to_string_lossy(), b.to_string_lossy());
+    let (mut a_chars, mut b_chars) = (a_lossy.chars().peekable(), b_lossy.chars().peekable());
+    loop {
+        let (a_char, b_char) = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(&a_char), Some(&b_char)) => (a_char, b_char),
+        };
+        if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+            let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                let mut digits = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                digits
+            };
+            let (a_digits, b_digits) = (take_number(&mut a_chars), take_number(&mut b_chars));
+            let (a_number, b_number) = (
+                a_digits.trim_start_matches('0'),
+                b_digits.trim_start_matches('0'),
+            );
+            // Numbers of any length, without parsing them
+            match a_number
+                .len()
+                .cmp(&b_number.len())
+                .then_with(|| a_number.cmp(b_number))
+            {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        match a_char.cmp(&b_char) {
+            Ordering::Equal => {
+                a_chars.next();
+                b_chars.next();
+            }
+            ordering => return ordering,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::os_from_bytes;
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        natural_cmp(OsStr::new(a), OsStr::new(b))
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(cmp("sub-2", "sub-10"), Ordering::Less);
+        assert_eq!(cmp("sub-10_run-2", "sub-10_run-1"), Ordering::Greater);
+        assert_eq!(cmp("a", "a1"), Ordering::Less);
+        // longer than u64
+        assert_eq!(
+            cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(cmp("sub-02", "sub-10"), Ordering::Less);
+        assert_eq!(cmp("sub-002", "sub-2"), Ordering::Less);
+        assert_eq!(cmp("sub-2", "sub-2"), Ordering::Equal);
+    }
+
+    // os_from_bytes is lossy outside of Unix
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8() {
+        let (a, b) = (
+            os_from_bytes(b"\xe9-2".to_vec()),
+            os_from_bytes(b"\xe9-10".to_vec()),
+        );
+        assert_eq!(natural_cmp(&a, &b), Ordering::Less);
+        // the same lossy names
+        let (a, b) = (
+            os_from_bytes(b"a\xfe".to_vec()),
+            os_from_bytes(b"a\xff".to_vec()),
+        );
+        assert_eq!(natural_cmp(&a, &b), Ordering::Less);
+        assert_eq!(natural_cmp(&b, &a), Ordering::Greater);
+    }
+}
@@ -0,0 +1,59 @@
+//! Setup of the [`tracing`] subscriber for the executables of the workspace.
+//!
+//! The library itself only emits the events and spans (a `file` span per converted volume
+//! and a `slice` span per exported slice). Logs are written to stderr, so they never mix with
+//! the stdout of the interactive prompts and of the [worker processes](crate::process_pool).
+
+use tracing_subscriber::EnvFilter;
+
+use crate::error_ty::ErrorTy::{self, *};
+
+/// Environment variable with the log level of the `nifti2png` executables or, more generally,
+/// [`EnvFilter`] directives, e.g. `debug` or `nifti2png=trace`
+pub const LOG_ENV_VAR: &str = "NIFTI2PNG_LOG";
+
+/// Environment variable with the [`LogFormat`] of the `nifti2png` executables: `text` or `json`
+pub const LOG_FORMAT_ENV_VAR: &str = "NIFTI2PNG_LOG_FORMAT";
+
+/// Format of the log records
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// A JSON object per line
+    Json,
+}
+
+/// Configuration of the subscriber installed by [`init`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogConfig {
+    /// [`EnvFilter`] directives, e.g. `debug` or `nifti2png=trace`. `info` by default.
+    pub directives: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            directives: "info".to_string(),
+            format: LogFormat::default(),
+        }
+    }
+}
+
+/// Installs the global subscriber.
+///
+/// Fails if the directives can't be parsed or another subscriber is already installed.
+pub fn init(config: &LogConfig) -> Result<(), ErrorTy> {
+    let filter =
+        EnvFilter::try_new(&config.directives).map_err(|e| LoggingSetupFailed(e.to_string()))?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match config.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| LoggingSetupFailed(e.to_string()))
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{volume_access::VolumeAccess, MAX_DIMS};
+
+/// Upper bound for the memory needed to convert a single volume
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryBudget {
+    pub bytes: u64,
+    pub on_exceed: OverBudget,
+}
+
+/// What to do with the volumes that don't fit into the [`MemoryBudget`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverBudget {
+    /// The volume fails to convert with `ErrorTy::OverMemoryBudget`
+    #[default]
+    Reject,
+    /// The volume is read slice by slice with [`VolumeAccess::Lazy`] if that fits into the budget
+    Stream,
+}
+
+/// Outcome of [`MemoryBudget::check`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BudgetDecision {
+    Accept(VolumeAccess),
+    Reject,
+}
+
+/// Estimates the peak memory needed to convert a volume of the given shape from the header.
+///
+/// `itemsize` is the size of the on-disk data type. Decoding a volume needs the on-disk
+/// data and the floating-point result at once. Every exported slice additionally goes through
+/// two float64 arrays (decoded and rescaled), a uint8 array and an RGB image.
+pub(crate) fn estimate_memory(
+    dims: &[isize; MAX_DIMS],
+    itemsize: u64,
+    access: VolumeAccess,
+) -> u64 {
+    let slice_voxels = (dims[0] * dims[1]) as u64;
+    let slice_bytes = slice_voxels * (itemsize + 2 * 8 + 1 + 3);
+    match access {
+        VolumeAccess::Lazy => slice_bytes,
+        VolumeAccess::Full(dtype) => {
+            let voxels = dims.iter().map(|d| *d as u64).product::<u64>();
+            voxels * (itemsize + dtype.itemsize()) + slice_bytes
+        }
+        VolumeAccess::Auto => estimate_memory(dims, itemsize, access.resolved()),
+    }
+}
+
+impl MemoryBudget {
+    pub(crate) fn check(
+        &self,
+        dims: &[isize; MAX_DIMS],
+        itemsize: u64,
+        access: VolumeAccess,
+    ) -> BudgetDecision {
+        let access = access.resolved();
+        if estimate_memory(dims, itemsize, access) <= self.bytes {
+            return BudgetDecision::Accept(access);
+        }
+        match (self.on_exceed, access) {
+            (OverBudget::Stream, VolumeAccess::Full(_))
+                if estimate_memory(dims, itemsize, VolumeAccess::Lazy) <= self.bytes =>
+            {
+                BudgetDecision::Accept(VolumeAccess::Lazy)
+            }
+            _ => BudgetDecision::Reject,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FloatDtype;
+
+    // 4 x 3 slices of int16, 2 x 2 of them
+    const DIMS: [isize; MAX_DIMS] = [4, 3, 2, 2];
+    const SLICE_BYTES: u64 = 12 * (2 + 2 * 8 + 1 + 3);
+
+    fn budget(bytes: u64, on_exceed: OverBudget) -> MemoryBudget {
+        MemoryBudget { bytes, on_exceed }
+    }
+
+    #[test]
+    fn estimate() {
+        assert_eq!(estimate_memory(&DIMS, 2, VolumeAccess::Lazy), SLICE_BYTES);
+        let float32 = estimate_memory(&DIMS, 2, VolumeAccess::Full(FloatDtype::Float32));
+        assert_eq!(float32, 48 * (2 + 4) + SLICE_BYTES);
+        let float64 = estimate_memory(&DIMS, 2, VolumeAccess::Full(FloatDtype::Float64));
+        assert_eq!(float64, 48 * (2 + 8) + SLICE_BYTES);
+        assert_eq!(estimate_memory(&DIMS, 2, VolumeAccess::Auto), float64);
+    }
+
+    #[test]
+    fn within_budget() {
+        let full = estimate_memory(&DIMS, 2, VolumeAccess::Auto);
+        for on_exceed in [OverBudget::Reject, OverBudget::Stream] {
+            assert_eq!(
+                budget(full, on_exceed).check(&DIMS, 2, VolumeAccess::Auto),
+                BudgetDecision::Accept(VolumeAccess::Full(FloatDtype::Float64))
+            );
+            assert_eq!(
+                budget(SLICE_BYTES, on_exceed).check(&DIMS, 2, VolumeAccess::Lazy),
+                BudgetDecision::Accept(VolumeAccess::Lazy)
+            );
+        }
+    }
+
+    #[test]
+    fn over_budget() {
+        let full = estimate_memory(&DIMS, 2, VolumeAccess::Auto);
+        for access in [VolumeAccess::Auto, VolumeAccess::Full(FloatDtype::Float64)] {
+            assert_eq!(
+                budget(full - 1, OverBudget::Reject).check(&DIMS, 2, access),
+                BudgetDecision::Reject
+            );
+            assert_eq!(
+                budget(full - 1, OverBudget::Stream).check(&DIMS, 2, access),
+                BudgetDecision::Accept(VolumeAccess::Lazy)
+            );
+            assert_eq!(
+                budget(SLICE_BYTES - 1, OverBudget::Stream).check(&DIMS, 2, access),
+                BudgetDecision::Reject
+            );
+        }
+        assert_eq!(
+            budget(SLICE_BYTES - 1, OverBudget::Stream).check(&DIMS, 2, VolumeAccess::Lazy),
+            BudgetDecision::Reject
+        );
+    }
+}
@@ -1,20 +1,79 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use pyo3::prelude::*;
 
+mod annotations;
+mod array_export;
+mod bids;
+mod convert_options;
+mod convert_report;
+mod dataset;
+mod dicom_series;
 mod error_ty;
-use error_ty::ErrorTy::{self, *};
+use error_ty::ErrorTy::*;
+mod export_mode;
+mod input_order;
+pub mod logging;
+mod memory_budget;
 mod nii_image;
+mod npy;
+mod observer;
+mod on_error;
+mod orientation;
+mod output_manifest;
+mod output_names;
+mod output_sink;
+mod overwrite_policy;
+mod paths;
+mod png2nifti;
+pub mod process_pool;
 mod rel_nii_files_iter;
 mod rel_nii_images_iter;
 mod rescaled_intensity_nii_image;
 mod rescaled_intensity_nii_slice;
+mod resume_manifest;
+mod slice_encoder_pool;
 pub mod target_path;
+mod ubyte_slice;
+mod volume_access;
+mod volume_exporter;
+mod volume_format;
+pub mod volumes;
+pub use annotations::{
+    export_annotations, AnnotationFormat, AnnotationOptions, AnnotationOptionsBuilder,
+    AnnotationReport, Categories,
+};
+pub use array_export::{ArrayContainer, ArrayDtype, ArrayExport, ArrayLayout};
+pub use bids::BidsFilter;
+pub use convert_options::{ConvertOptions, ConvertOptionsBuilder};
+pub use convert_report::ConvertReport;
+pub use dataset::{
+    export_dataset, DatasetOptions, DatasetOptionsBuilder, DatasetReport, Split, SplitRatios,
+};
+pub use error_ty::ErrorTy;
+pub use export_mode::ExportMode;
+pub use input_order::InputOrder;
+pub use memory_budget::{MemoryBudget, OverBudget};
+pub use observer::{ConsoleObserver, ConvertObserver, SilentObserver};
+pub use on_error::OnError;
+pub use output_manifest::ManifestFormat;
+pub use output_names::NameCollision;
+pub use output_sink::{ArchiveFormat, ArchiveSink, DirectorySink, MemorySink, OutputSink, S3Sink};
+pub use overwrite_policy::OverwritePolicy;
+pub use png2nifti::png2nifti;
 use rel_nii_images_iter::RelNiiImagesIter;
+pub use volume_access::{FloatDtype, VolumeAccess};
 
 use crate::{
-    nii_image::NiiImage, rescaled_intensity_nii_image::RescaledIntensityNiiImage,
-    target_path::TargetImageDir,
+    nii_image::NiiImage,
+    output_manifest::OutputManifest,
+    resume_manifest::{Fingerprint, ResumeManifest},
+    slice_encoder_pool::SliceEncoderPool,
+    target_path::{ExistingImageDir, TargetImageDir},
+    volume_exporter::VolumeExporter,
 };
 
 /// Expected number of dimensions in images.
@@ -28,84 +87,325 @@ const SECONDARY_DIMS: usize = MAX_DIMS - 2;
 /// - The original code uses concatenation with `\\` to construct paths.
 /// - The original code does not format numbers with leading zeros.
 /// - The original code ignored the warning about lossy conversion:
-/// Lossy conversion from float64 to uint8. Range \[0, 1\]. Convert image to uint8 prior to saving to suppress this warning.
-/// Learn more about the warning [here](https://github.com/zhixuhao/unet/issues/125).
+///   Lossy conversion from float64 to uint8. Range \[0, 1\]. Convert image to uint8 prior to saving to suppress this warning.
+///   Learn more about the warning [here](https://github.com/zhixuhao/unet/issues/125).
 /// - The original code contains the
-/// [dead code with `nii_stub`](https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L69-L71)
+///   [dead code with `nii_stub`](https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L69-L71)
 /// - The original code used np.min() and np.max() to calculate min and max values of the image
-/// to later then pass them as `in_range` optional parameter to `skimage.exposure.rescale_intensity`.
+///   to later then pass them as `in_range` optional parameter to `skimage.exposure.rescale_intensity`.
 /// - The original code iterated over the 4th dimension of the image but only the last 3D slice was used.
-pub fn convert(
-    nii_files: &str,
-    png_stub: Option<&str>,
-    minmax: Option<(u64, u64)>,
-) -> Result<(), ErrorTy> {
-    Python::with_gil(|py| {
-        let os = py.import("os").map_err(MissingStandardLibrary)?;
-        let nib = py.import("nibabel").map_err(MissingThirdPartyLibrary)?;
-        let (io, color, exposure, img_as_ubyte) = {
-            let skimage = py.import("skimage").map_err(MissingThirdPartyLibrary)?;
-            (
-                skimage
-                    .getattr("io")
-                    .map_err(MissingComponentOfThirdPartyLibrary)?,
-                skimage
-                    .getattr("color")
-                    .map_err(MissingComponentOfThirdPartyLibrary)?,
-                skimage
-                    .getattr("exposure")
-                    .map_err(MissingComponentOfThirdPartyLibrary)?,
-                skimage
-                    .getattr("img_as_ubyte")
-                    .map_err(MissingComponentOfThirdPartyLibrary)?,
-            )
-        };
+/// - The original code processed the slices sequentially. See [`ExportMode`] for alternatives.
+/// - The original code decoded the whole volumes with `get_fdata`. See [`VolumeAccess`] for alternatives.
+/// - The original code could run out of memory on large volumes. See [`MemoryBudget`] for a guard.
+/// - The original code printed the progress unconditionally. See [`convert_with_observer`] for alternatives.
+/// - The original code stopped at the first file that failed to convert. See [`OnError`] for alternatives.
+pub fn convert(options: &ConvertOptions) -> Result<ConvertReport, ErrorTy> {
+    convert_with_observer(options, &mut ConsoleObserver)
+}
 
-        // https://github.com/PyO3/pyo3/discussions/3001
+/// Same as [`convert`] but the progress is reported to `observer` instead of stdout
+pub fn convert_with_observer(
+    options: &ConvertOptions,
+    observer: &mut dyn ConvertObserver,
+) -> Result<ConvertReport, ErrorTy> {
+    Python::with_gil(|py| convert_with_py(py, options, observer))
+}
 
-        #[allow(non_snake_case)]
-        let Image = py.import("PIL.Image").map_err(MissingThirdPartyLibrary)?;
-        #[allow(non_snake_case)]
-        let ImageOps = py
-            .import("PIL.ImageOps")
-            .map_err(MissingThirdPartyLibrary)?;
+/// Same as [`convert_with_observer`] but for callers that already hold the GIL
+pub fn convert_with_py(
+    py: Python<'_>,
+    options: &ConvertOptions,
+    observer: &mut dyn ConvertObserver,
+) -> Result<ConvertReport, ErrorTy> {
+    let res = convert_files(py, options, observer);
+    match &res {
+        Ok(report) => observer.finished(report),
+        Err(e) => {
+            tracing::error!(error = %e, "The conversion failed");
+            observer.error(e)
+        }
+    };
+    res
+}
 
-        // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L61-L64
-        let png_stub = PathBuf::from(png_stub.unwrap_or("slice"));
+fn convert_files(
+    py: Python<'_>,
+    options: &ConvertOptions,
+    observer: &mut dyn ConvertObserver,
+) -> Result<ConvertReport, ErrorTy> {
+    let ConvertOptions {
+        nii_files,
+        png_stub,
+        minmax,
+        export_mode,
+        on_error,
+        resume,
+        output_manifest,
+        overwrite,
+        output_sink,
+        bids,
+        array_export,
+        ..
+    } = options;
+    let sink: Arc<dyn OutputSink> = output_sink
+        .clone()
+        .unwrap_or_else(|| Arc::new(DirectorySink));
+    let os = py.import("os").map_err(MissingStandardLibrary)?;
+    let nib = py.import("nibabel").map_err(MissingThirdPartyLibrary)?;
+    let (io, color, exposure, img_as_ubyte) = {
+        let skimage = py.import("skimage").map_err(MissingThirdPartyLibrary)?;
+        (
+            skimage
+                .getattr("io")
+                .map_err(MissingComponentOfThirdPartyLibrary)?,
+            skimage
+                .getattr("color")
+                .map_err(MissingComponentOfThirdPartyLibrary)?,
+            skimage
+                .getattr("exposure")
+                .map_err(MissingComponentOfThirdPartyLibrary)?,
+            skimage
+                .getattr("img_as_ubyte")
+                .map_err(MissingComponentOfThirdPartyLibrary)?,
+        )
+    };
 
-        for res in RelNiiImagesIter::new(nib, os, nii_files, png_stub)? {
-            let (png_stub, nii_image): (TargetImageDir, NiiImage) = res?;
-            TargetImageDir::ensure_exists(&png_stub)?;
+    // https://github.com/PyO3/pyo3/discussions/3001
 
-            println!("\tMatrix size: ({:?})", nii_image.dims);
+    #[allow(non_snake_case)]
+    let Image = py.import("PIL.Image").map_err(MissingThirdPartyLibrary)?;
+    #[allow(non_snake_case)]
+    let ImageOps = py
+        .import("PIL.ImageOps")
+        .map_err(MissingThirdPartyLibrary)?;
 
-            let nii_image: RescaledIntensityNiiImage =
-                nii_image.rescale_intensity_to_unit_interval(py, exposure, minmax)?;
+    let (mut manifest, completed) = match resume {
+        true => {
+            let (manifest, completed) =
+                ResumeManifest::open(&TargetImageDir(PathBuf::from(png_stub)), options)?;
+            (Some(manifest), Some(completed))
+        }
+        false => (None, None),
+    };
 
-            for t in 0..nii_image.dim(MAX_DIMS - 1) {
-                println!("\tVolume {t} -> {}", png_stub.path.display());
+    let mut exporter = VolumeExporter {
+        io,
+        color,
+        exposure,
+        img_as_ubyte,
+        Image,
+        ImageOps,
+        minmax: *minmax,
+        pool: match (*export_mode, array_export) {
+            (ExportMode::Parallel(threads), None) => {
+                Some(SliceEncoderPool::new(threads, *on_error, Arc::clone(&sink)))
+            }
+            _ => None,
+        },
+        sink: output_sink.clone(),
+        arrays: *array_export,
+    };
+    let mut output_manifest = match output_manifest {
+        Some(format) => Some(OutputManifest::create(
+            py,
+            &TargetImageDir(PathBuf::from(png_stub)),
+            *format,
+            *resume,
+            &*sink,
+        )?),
+        None => None,
+    };
+    if bids.is_some() {
+        sink.write(
+            &png_stub.join("dataset_description.json"),
+            &bids::dataset_description(nii_files)?,
+        )?;
+    }
+    let mut rows = Vec::new();
+    let mut report = ConvertReport::default();
+    // Converted sources (with their fingerprints) whose slices may still be saved by the encoder threads.
+    // Their directories are marked complete and recorded in the manifest only once all slices are saved.
+    let mut unsaved: Vec<(ExistingImageDir, PathBuf, Option<Fingerprint>)> = Vec::new();
 
-                for z in 0..nii_image.dim(MAX_DIMS - 2) {
-                    // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L119-L122
-                    // Current volume
-                    let nii_slice = nii_image.get_slice(py, [z, t])?;
+    let mut nii_images = RelNiiImagesIter::new(nib, os, options, completed)?;
+    loop {
+        // Python objects are owned by the innermost GIL pool and live until it is dropped,
+        // so every file and every slice gets a pool of its own. Otherwise, the memory would
+        // grow with every converted file. https://pyo3.rs/v0.18.1/memory.html
+        //
+        // SAFETY: nothing created while the pool exists is used after it is dropped.
+        // The iterator keeps only the objects created before (or owned ones) across iterations.
+        let file_gil_pool = unsafe { py.new_pool() };
+        let py = file_gil_pool.python();
 
-                    // PNG filename
-                    let png_path = os.getattr("path")?.call_method(
-                        "join",
-                        (&png_stub.path, format!("{z:04}.png")),
-                        None,
-                    )?;
+        // The next volume isn't even loaded once a slice has failed to save
+        if exporter
+            .pool
+            .as_ref()
+            .is_some_and(SliceEncoderPool::is_stopped)
+        {
+            if let Some(stopped_pool) = exporter.pool.take() {
+                py.allow_threads(|| stopped_pool.finish())?;
+            }
+            return Err(EncoderThreadsStopped);
+        }
+        let (done, total) = nii_images.progress();
+        observer.entries_progress(done, total);
+        let res = nii_images.next();
+        report.skipped.extend(nii_images.take_skipped());
+        let Some(res) = res else {
+            break;
+        };
+        let (png_stub, source, nii_image): (TargetImageDir, PathBuf, NiiImage) = match res {
+            Ok(item) => item,
+            Err(e) => {
+                record_failure(e, *on_error, &mut report, observer)?;
+                continue;
+            }
+        };
+        let _file_span = tracing::info_span!("file", source = %source.display()).entered();
+        let fingerprint = nii_images.take_fingerprint(&png_stub.path);
+
+        let prepared = match sink.is_local() {
+            true => png_stub.prepare(*overwrite),
+            false => Ok(Some(png_stub.in_remote_sink())),
+        };
+        let target_dir = match prepared {
+            Ok(Some(target_dir)) => target_dir,
+            Ok(None) => {
+                tracing::info!(target_dir = %png_stub.path.display(), "Skipped the existing export");
+                report.skipped.push(source);
+                continue;
+            }
+            Err(e) => {
+                record_failure(e.in_file(source), *on_error, &mut report, observer)?;
+                continue;
+            }
+        };
+        if bids.is_some() {
+            let written = bids::derivative_sidecar(nii_files, &source)
+                .and_then(|sidecar| sink.write(&bids::sidecar_path(&target_dir.path), &sidecar));
+            if let Err(e) = written {
+                record_failure(e.in_file(source), *on_error, &mut report, observer)?;
+                continue;
+            }
+        }
 
-                    nii_slice.save(png_path, io, color, img_as_ubyte, &Image, &ImageOps)?;
-                    // let buffer = nii_slice.as_raw_rgb_image_buffer(py, io, color, img_as_ubyte, Image, ImageOps)?;
-                    // println!("Buffer: {:?}", buffer);
+        rows.clear();
+        let file_rows = output_manifest.as_ref().map(|_| &mut rows);
+        match exporter.export(py, &target_dir, &source, nii_image, observer, file_rows) {
+            Ok(()) => {
+                if let Some(output_manifest) = &mut output_manifest {
+                    output_manifest.add_rows(&target_dir.path, &mut rows);
+                }
+                observer.file_finished(&source);
+                report.record_converted(source.clone(), target_dir.path.clone());
+                unsaved.push((target_dir, source, fingerprint));
+            }
+            Err(EncoderThreadsStopped) => {
+                // The threads have stopped, most likely because of an error of their own
+                if let Some(stopped_pool) = exporter.pool.take() {
+                    py.allow_threads(|| stopped_pool.finish())?;
                 }
+                return Err(EncoderThreadsStopped);
             }
+            Err(e) => record_failure(e.in_file(source), *on_error, &mut report, observer)?,
         }
 
-        println!("Done");
+        let mut res = Ok(());
+        unsaved.retain(|(target_dir, source, fingerprint)| {
+            let written = match &exporter.pool {
+                Some(pool) => pool.take_written(&target_dir.path),
+                None => Some(true),
+            };
+            if written == Some(true) && res.is_ok() {
+                res = complete(
+                    target_dir,
+                    source,
+                    fingerprint.as_ref(),
+                    &*sink,
+                    manifest.as_mut(),
+                    output_manifest.as_mut(),
+                );
+            }
+            written.is_none()
+        });
+        res?;
+    }
+    // The entries after the last volume, if any, are skipped
+    let (_, total) = nii_images.progress();
+    observer.entries_progress(total, total);
 
-        Ok(())
-    })
+    if let Some(pool) = exporter.pool.take() {
+        // With `OnError::Continue`, the slices that failed to save don't stop the threads
+        for (failed_dir, source, e) in py.allow_threads(|| pool.finish())? {
+            unsaved.retain(|(target_dir, ..)| target_dir.path != failed_dir);
+            if let Some(output_manifest) = &mut output_manifest {
+                output_manifest.discard(&failed_dir);
+            }
+            // Only the first slice that failed to save is reported
+            if report.take_converted(&failed_dir) {
+                record_failure(e.in_file(source), *on_error, &mut report, observer)?;
+            }
+        }
+    }
+    for (target_dir, source, fingerprint) in unsaved {
+        complete(
+            &target_dir,
+            &source,
+            fingerprint.as_ref(),
+            &*sink,
+            manifest.as_mut(),
+            output_manifest.as_mut(),
+        )?;
+    }
+    if let Some(output_manifest) = output_manifest {
+        output_manifest.finish()?;
+    }
+    sink.finish()?;
+
+    Ok(report)
+}
+
+/// Marks the directory of a source whose slices are all saved complete, lists its images
+/// in the output manifest and records the source with its `fingerprint` in the resume manifest,
+/// if any. The images are listed first, since the next run skips the recorded sources.
+fn complete(
+    target_dir: &ExistingImageDir,
+    source: &Path,
+    fingerprint: Option<&Fingerprint>,
+    sink: &dyn OutputSink,
+    manifest: Option<&mut ResumeManifest>,
+    output_manifest: Option<&mut OutputManifest>,
+) -> Result<(), ErrorTy> {
+    target_dir.mark_complete(sink)?;
+    if let Some(output_manifest) = output_manifest {
+        output_manifest.complete(&target_dir.path)?;
+    }
+    match (manifest, fingerprint) {
+        (Some(manifest), Some(fingerprint)) => {
+            manifest.record(&target_dir.path, source, fingerprint)
+        }
+        // Converted again by the next run
+        _ => Ok(()),
+    }
+}
+
+/// Records the failure of a single file in the report with [`OnError::Continue`].
+/// Returns the error otherwise or if it doesn't belong to a single file.
+fn record_failure(
+    e: ErrorTy,
+    on_error: OnError,
+    report: &mut ConvertReport,
+    observer: &mut dyn ConvertObserver,
+) -> Result<(), ErrorTy> {
+    match (e, on_error) {
+        (FileFailed(source, e), OnError::Continue) => {
+            tracing::warn!(source = %source.display(), error = %e, "Failed to convert the file");
+            observer.file_failed(&source, &e);
+            report.record_failure(source, *e);
+            Ok(())
+        }
+        (e, _) => Err(e),
+    }
 }
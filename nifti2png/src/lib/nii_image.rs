@@ -1,44 +1,63 @@
 use arrayvec::ArrayVec;
-use pyo3::{
-    prelude::*,
-    types::{PyDict, PySlice},
-};
+use pyo3::{prelude::*, types::PySlice};
 
-use crate::{error_ty::ErrorTy, rescaled_intensity_nii_image::RescaledIntensityNiiImage, MAX_DIMS, SECONDARY_DIMS};
+use crate::{
+    error_ty::ErrorTy, rescaled_intensity_nii_image::RescaledIntensityNiiImage,
+    volume_access::VolumeAccess, MAX_DIMS, SECONDARY_DIMS,
+};
 
 /// Loaded NIFTI image
 pub(crate) struct NiiImage<'a> {
-    pub(crate) fdata: &'a PyAny,
+    // `nii_obj.header`
+    pub(crate) hdr: &'a PyAny,
+    // `nii_obj.affine`, i.e. voxel to world-space coordinates
+    pub(crate) affine: [[f64; 4]; 4],
+    // Either nibabel's array proxy (`nii_obj.dataobj`) or the decoded volume, depending on `access`.
+    // Both are sliced the same way.
+    pub(crate) data: &'a PyAny,
     pub(crate) dims: ArrayVec<isize, MAX_DIMS>,
+    pub(crate) access: VolumeAccess,
 }
 
 impl<'a> NiiImage<'a> {
+    /// Minimum and maximum intensity of the whole volume
+    fn intensity_range(&self, py: Python<'a>) -> Result<(f64, f64), ErrorTy> {
+        if let VolumeAccess::Full(_) = self.access {
+            let min = self.data.call_method0("min")?.extract::<f64>()?;
+            let max = self.data.call_method0("max")?.extract::<f64>()?;
+            return Ok((min, max));
+        }
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for t in 0..self.dims[MAX_DIMS - 1] {
+            for z in 0..self.dims[MAX_DIMS - 2] {
+                // SAFETY: only the extracted numbers leave the iteration
+                let slice_gil_pool = unsafe { py.new_pool() };
+                let py = slice_gil_pool.python();
+                let slice = self.get_slice(py, [z, t])?;
+                min = min.min(slice.call_method0("min")?.extract::<f64>()?);
+                max = max.max(slice.call_method0("max")?.extract::<f64>()?);
+            }
+        }
+        Ok((min, max))
+    }
+
     pub(crate) fn rescale_intensity_to_unit_interval(
-        mut self,
+        self,
         py: Python<'a>,
         exposure: &'a PyAny,
         minmax: Option<(u64, u64)>,
     ) -> Result<RescaledIntensityNiiImage<'a>, ErrorTy> {
-        // Rescale to 0..255 uint8
-        self.fdata = exposure.call_method(
-            "rescale_intensity",
-            (self.fdata,),
-            Some({
-                let dict = PyDict::new(py);
-                // Clamp input range if requested
-                match minmax {
-                    Some((imin, imax)) => {
-                        dict.set_item("in_range", (imin, imax))?;
-                    }
-                    None => (),
-                }
-                dict.set_item("out_range", (0.0, 1.0))?;
-                dict
-            }),
-        )?;
-        Ok(RescaledIntensityNiiImage::new(self))
+        // Rescaling every slice with the range of the whole volume
+        // is the same as rescaling the whole volume at once.
+        let in_range = match minmax {
+            // Clamp input range if requested
+            Some((imin, imax)) => (imin as f64, imax as f64),
+            None => self.intensity_range(py)?,
+        };
+        Ok(RescaledIntensityNiiImage::new(self, exposure, in_range))
     }
 
+    /// Returns the slice as a float64 ndarray
     pub(crate) fn get_slice(
         &self,
         py: Python<'a>,
@@ -46,7 +65,7 @@ impl<'a> NiiImage<'a> {
     ) -> Result<&'a PyAny, ErrorTy> {
         debug_assert!(index.len() == self.dims.len() - 2);
         let slice = if self.dims[MAX_DIMS - 1] > 1 {
-            self.fdata.get_item((
+            self.data.get_item((
                 // Slicing using : is not supported in PyO3 yet
                 // https://github.com/PyO3/pyo3/issues/3000
                 PySlice::new(py, 0, self.dims[0], 1),
@@ -55,12 +74,13 @@ impl<'a> NiiImage<'a> {
                 index[1],
             ))?
         } else {
-            self.fdata.get_item((
+            self.data.get_item((
                 PySlice::new(py, 0, self.dims[0], 1),
                 PySlice::new(py, 0, self.dims[1], 1),
                 index[0],
             ))?
         };
-        Ok(slice)
+        // The array proxy returns the slice in the on-disk dtype (scaled by scl_slope and scl_inter)
+        Ok(slice.call_method1("astype", ("float64",))?)
     }
 }
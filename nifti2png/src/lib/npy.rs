@@ -0,0 +1,175 @@
+//! [NPY](https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html) and NPZ encoding,
+//! so that the arrays don't make a round trip through NumPy
+
+use std::io::{Cursor, Write};
+
+use crate::error_ty::ErrorTy::{self, *};
+
+/// Elements of an array in the C order
+pub(crate) enum NpyData {
+    Float32(Vec<f32>),
+    Uint16(Vec<u16>),
+    Uint8(Vec<u8>),
+}
+
+impl NpyData {
+    /// `descr` of the header, always little-endian
+    fn descr(&self) -> &'static str {
+        match self {
+            NpyData::Float32(_) => "<f4",
+            NpyData::Uint16(_) => "<u2",
+            NpyData::Uint8(_) => "|u1",
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            NpyData::Float32(data) => data.len(),
+            NpyData::Uint16(data) => data.len(),
+            NpyData::Uint8(data) => data.len(),
+        }
+    }
+
+    /// Appends the elements of `other`, which must have the same dtype
+    pub(crate) fn extend(&mut self, other: NpyData) {
+        match (self, other) {
+            (NpyData::Float32(data), NpyData::Float32(other)) => data.extend(other),
+            (NpyData::Uint16(data), NpyData::Uint16(other)) => data.extend(other),
+            (NpyData::Uint8(data), NpyData::Uint8(other)) => data.extend(other),
+            _ => unreachable!("the slices of a volume have the same dtype"),
+        }
+    }
+
+    fn write_le(&self, out: &mut Vec<u8>) {
+        match self {
+            NpyData::Float32(data) => out.extend(data.iter().flat_map(|v| v.to_le_bytes())),
+            NpyData::Uint16(data) => out.extend(data.iter().flat_map(|v| v.to_le_bytes())),
+            NpyData::Uint8(data) => out.extend_from_slice(data),
+        }
+    }
+}
+
+/// Encodes the array as an NPY file of version 1.0
+pub(crate) fn encode(shape: &[usize], data: &NpyData) -> Vec<u8> {
+    debug_assert_eq!(shape.iter().product::<usize>(), data.len());
+    let shape = match shape {
+        [len] => format!("({len},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {shape}, }}",
+        data.descr()
+    );
+    // The magic string, the version and the length of the header come first,
+    // and the data must start at a multiple of 64 bytes
+    let unpadded = 10 + header.len() + 1;
+    header.extend(std::iter::repeat_n(' ', (64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut npy = Vec::with_capacity(10 + header.len() + data.len() * 4);
+    npy.extend_from_slice(b"\x93NUMPY\x01\x00");
+    npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    npy.extend_from_slice(header.as_bytes());
+    data.write_le(&mut npy);
+    npy
+}
+
+/// Deflate-compressed NPZ file, i.e. what `numpy.savez_compressed` writes
+pub(crate) struct NpzWriter {
+    zip: zip::ZipWriter<Cursor<Vec<u8>>>,
+    // Path of the file, for the errors
+    path: String,
+}
+
+impl NpzWriter {
+    pub(crate) fn new(path: String) -> Self {
+        Self {
+            zip: zip::ZipWriter::new(Cursor::new(Vec::new())),
+            path,
+        }
+    }
+
+    fn write_failed(&self, e: impl Into<std::io::Error>) -> ErrorTy {
+        SinkWriteFailed(e.into(), self.path.clone())
+    }
+
+    /// Adds the NPY file as the array `name`
+    pub(crate) fn add(&mut self, name: &str, npy: &[u8]) -> Result<(), ErrorTy> {
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .large_file(npy.len() as u64 >= u32::MAX as u64);
+        self.zip
+            .start_file(format!("{name}.npy"), options)
+            .map_err(|e| self.write_failed(e))?;
+        self.zip.write_all(npy).map_err(|e| self.write_failed(e))
+    }
+
+    pub(crate) fn finish(mut self) -> Result<Vec<u8>, ErrorTy> {
+        match self.zip.finish() {
+            Ok(npz) => Ok(npz.into_inner()),
+            Err(e) => Err(self.write_failed(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    /// The header and the data of the NPY file
+    fn split(npy: &[u8]) -> (&str, &[u8]) {
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        let len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        let header = std::str::from_utf8(&npy[10..10 + len]).unwrap();
+        (header, &npy[10 + len..])
+    }
+
+    #[test]
+    fn one_dimensional() {
+        let npy = encode(&[3], &NpyData::Uint8(vec![1, 2, 3]));
+        let (header, data) = split(&npy);
+        assert_eq!((10 + header.len()) % 64, 0);
+        assert!(header.starts_with("{'descr': '|u1', 'fortran_order': False, 'shape': (3,), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(data, [1, 2, 3]);
+    }
+
+    #[test]
+    fn little_endian_and_padded() {
+        for shape in [[1, 2, 1], [10, 100, 1000]] {
+            let data = NpyData::Float32(vec![0.0; shape.iter().product()]);
+            let npy = encode(&shape, &data);
+            let (header, _) = split(&npy);
+            assert_eq!((10 + header.len()) % 64, 0, "{shape:?}");
+        }
+        let npy = encode(&[2, 1], &NpyData::Uint16(vec![1, 0x0203]));
+        let (header, data) = split(&npy);
+        assert!(header.starts_with("{'descr': '<u2', 'fortran_order': False, 'shape': (2, 1), }"));
+        assert_eq!(data, [1, 0, 3, 2]);
+    }
+
+    #[test]
+    fn npz_entries() {
+        let npy = encode(&[1], &NpyData::Float32(vec![1.5]));
+        let mut npz = NpzWriter::new("a.npz".to_string());
+        npz.add("0000", &npy).unwrap();
+        npz.add("0001", &npy).unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(npz.finish().unwrap())).unwrap();
+        assert_eq!(zip.len(), 2);
+        let mut entry = Vec::new();
+        zip.by_name("0001.npy")
+            .unwrap()
+            .read_to_end(&mut entry)
+            .unwrap();
+        assert_eq!(entry, npy);
+    }
+}
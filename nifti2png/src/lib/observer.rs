@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use crate::{error_ty::ErrorTy, ConvertReport};
+
+/// Receives the progress of [`convert_with_observer`](crate::convert_with_observer).
+///
+/// All methods do nothing by default, so implementations only override the events they need.
+pub trait ConvertObserver {
+    /// `done` of the `total` entries of `nii_files` are handled, the skipped and the failed ones included.
+    /// A DICOM series folder is a single entry, which is done once all its series are.
+    fn entries_progress(&mut self, _done: usize, _total: usize) {}
+
+    /// A volume is loaded. `dims` are the dimensions of the volume, 3D volumes have a single `t`.
+    fn file_started(&mut self, _source: &Path, _target_dir: &Path, _dims: [usize; 4]) {}
+
+    /// Slices of the 3D volume `t` are about to be exported
+    fn volume_started(&mut self, _t: usize, _target_dir: &Path) {}
+
+    /// The slice is saved to `path`.
+    /// With [`ExportMode::Parallel`](crate::ExportMode::Parallel), it is handed over
+    /// to the encoder threads and may still be in progress.
+    fn slice_written(&mut self, _path: &Path) {}
+
+    fn file_finished(&mut self, _source: &Path) {}
+
+    /// The file failed to convert and is skipped with [`OnError::Continue`](crate::OnError::Continue)
+    fn file_failed(&mut self, _source: &Path, _error: &ErrorTy) {}
+
+    /// The conversion stopped because of `error`
+    fn error(&mut self, _error: &ErrorTy) {}
+
+    /// All volumes are processed
+    fn finished(&mut self, _report: &ConvertReport) {}
+}
+
+/// Prints the same progress messages as the original Python code to stdout
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConsoleObserver;
+
+impl ConvertObserver for ConsoleObserver {
+    fn file_started(&mut self, _source: &Path, _target_dir: &Path, dims: [usize; 4]) {
+        println!("\tMatrix size: ({dims:?})");
+    }
+
+    fn volume_started(&mut self, t: usize, target_dir: &Path) {
+        println!("\tVolume {t} -> {}", target_dir.display());
+    }
+
+    fn file_failed(&mut self, source: &Path, error: &ErrorTy) {
+        println!("\tFailed to convert {}: {error}", source.display());
+    }
+
+    fn finished(&mut self, _report: &ConvertReport) {
+        println!("Done");
+    }
+}
+
+/// Ignores all events
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SilentObserver;
+
+impl ConvertObserver for SilentObserver {}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+/// What [`convert`](crate::convert) does when a file fails to convert
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OnError {
+    /// The conversion stops with the error of the file, just like in the original Python code
+    #[default]
+    Stop,
+    /// The file is recorded in the [`ConvertReport`](crate::ConvertReport)
+    /// and the conversion goes on with the next one.
+    ///
+    /// Errors that don't belong to a single file, e.g. missing Python libraries,
+    /// stop the conversion regardless.
+    Continue,
+}
@@ -0,0 +1,119 @@
+//! Orientation of the exported slices.
+//!
+//! A `(height, width)` slice is first saved with `skimage.io.imsave` as is, i.e. the first
+//! axis of the slice becomes the rows of the image. Then the image is rotated with
+//! `PIL.Image.rotate(90)` and mirrored with `PIL.ImageOps.mirror`.
+//!
+//! Since `rotate` is called without `expand=True`, non-square images keep their size:
+//! the rotation happens around the center of the image, the parts that leave the canvas
+//! are cropped and the uncovered parts are filled with black. Hence, for non-square
+//! slices some voxels never make it to the PNG.
+
+/// Returns the `[x, y]` coordinates of the pixel of the exported PNG where the voxel
+/// `[row, col]` of a `height` x `width` slice ends up or `None` if the voxel is cropped away.
+///
+/// Mirrors the nearest-neighbour affine transform used by `PIL.Image.rotate(90)`
+/// followed by `PIL.ImageOps.mirror`.
+pub(crate) fn voxel2pixel(
+    height: usize,
+    width: usize,
+    [row, col]: [usize; 2],
+) -> Option<[usize; 2]> {
+    let (h, w) = (height as isize, width as isize);
+    let (row, col) = (row as isize, col as isize);
+    let y = (w + h - 1).div_euclid(2) - col;
+    let x = w - 1 - row + (h - w + 1).div_euclid(2);
+    if (0..w).contains(&x) && (0..h).contains(&y) {
+        Some([x as usize, y as usize])
+    } else {
+        None
+    }
+}
+
+/// Returns the `[row, col]` index of the voxel of a `height` x `width` slice that ends up
+/// at the pixel `[x, y]` of the exported PNG or `None` if the pixel is filled with black.
+///
+/// The inverse of [`voxel2pixel`].
+pub(crate) fn pixel2voxel(height: usize, width: usize, [x, y]: [usize; 2]) -> Option<[usize; 2]> {
+    let (h, w) = (height as isize, width as isize);
+    let [row, col] = pixel2grid(height, width, [x, y]);
+    if (0..h).contains(&row) && (0..w).contains(&col) {
+        Some([row as usize, col as usize])
+    } else {
+        None
+    }
+}
+
+/// Same as [`pixel2voxel`] but the black pixels get the indices the voxel grid would have there
+pub(crate) fn pixel2grid(height: usize, width: usize, [x, y]: [usize; 2]) -> [isize; 2] {
+    let (h, w) = (height as isize, width as isize);
+    let (x, y) = (x as isize, y as isize);
+    [
+        w - 1 - x + (h - w + 1).div_euclid(2),
+        (w + h - 1).div_euclid(2) - y,
+    ]
+}
+
+/// Lays the C-ordered voxels of a `height` x `width` slice out like the pixels of the exported PNG,
+/// row by row. The pixels without a voxel are `T::default()`.
+pub(crate) fn orient<T: Copy + Default>(voxels: &[T], [height, width]: [usize; 2]) -> Vec<T> {
+    let mut pixels = vec![T::default(); width * height];
+    for y in 0..height {
+        for x in 0..width {
+            if let Some([row, col]) = pixel2voxel(height, width, [x, y]) {
+                pixels[y * width + x] = voxels[row * width + col];
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZES: [[usize; 2]; 6] = [[1, 1], [2, 2], [3, 3], [2, 5], [5, 2], [4, 7]];
+
+    #[test]
+    fn square_slices() {
+        // rotated counterclockwise and mirrored, i.e. the row becomes the flipped x
+        // and the column the flipped y
+        assert_eq!(voxel2pixel(2, 2, [0, 0]), Some([1, 1]));
+        assert_eq!(voxel2pixel(2, 2, [0, 1]), Some([1, 0]));
+        assert_eq!(voxel2pixel(2, 2, [1, 0]), Some([0, 1]));
+        assert_eq!(
+            orient(&[1, 2, 3, 4, 5, 6, 7, 8, 9], [3, 3]),
+            [9, 6, 3, 8, 5, 2, 7, 4, 1]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for [height, width] in SIZES {
+            let mut visible = 0;
+            for row in 0..height {
+                for col in 0..width {
+                    if let Some(pixel) = voxel2pixel(height, width, [row, col]) {
+                        assert_eq!(pixel2voxel(height, width, pixel), Some([row, col]));
+                        visible += 1;
+                    }
+                }
+            }
+            // the part of the rotated slice that stays on the canvas
+            assert_eq!(visible, height.min(width).pow(2), "{height}x{width}");
+
+            let mut black = 0;
+            for y in 0..height {
+                for x in 0..width {
+                    match pixel2voxel(height, width, [x, y]) {
+                        Some(voxel) => {
+                            assert_eq!(voxel2pixel(height, width, voxel), Some([x, y]))
+                        }
+                        None => black += 1,
+                    }
+                }
+            }
+            assert_eq!(black, height * width - visible, "{height}x{width}");
+        }
+    }
+}
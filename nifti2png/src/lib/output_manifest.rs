@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    target_path::TargetImageDir,
+    OutputSink,
+};
+
+/// Format of the manifest of the exported images, see [`ConvertOptionsBuilder::output_manifest`](crate::ConvertOptionsBuilder::output_manifest)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ManifestFormat {
+    /// `manifest.csv` with a row per image and `versions.json` with the versions of the Python packages
+    Csv,
+    /// `manifest.json` with the versions of the Python packages and the list of images
+    Json,
+}
+
+/// Python packages whose versions are recorded in the manifest
+const PACKAGES: [&str; 5] = ["nibabel", "numpy", "skimage", "PIL", "pydicom"];
+
+/// Versions of the [`PACKAGES`] that can be imported
+fn package_versions(py: Python<'_>) -> BTreeMap<&'static str, String> {
+    PACKAGES
+        .into_iter()
+        .filter_map(|package| {
+            let version = py
+                .import(package)
+                .and_then(|module| module.getattr("__version__"))
+                .and_then(|version| version.extract::<String>());
+            version.ok().map(|version| (package, version))
+        })
+        .collect()
+}
+
+/// Exported image
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ManifestRow {
+    // the paths are lossy if they aren't UTF-8
+    pub(crate) source: String,
+    pub(crate) t: usize,
+    pub(crate) z: usize,
+    // world-space (scanner) coordinates of the voxel at the top left pixel of the image, in mm
+    pub(crate) position_x: f64,
+    pub(crate) position_y: f64,
+    pub(crate) position_z: f64,
+    pub(crate) path: String,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    // intensities mapped to black and white
+    pub(crate) window_min: f64,
+    pub(crate) window_max: f64,
+}
+
+#[derive(Serialize)]
+struct JsonManifest<'a> {
+    versions: &'a BTreeMap<&'static str, String>,
+    images: Vec<&'a ManifestRow>,
+}
+
+/// The images of [`JsonManifest`], the versions are recorded anew
+#[derive(Deserialize)]
+struct PreviousJsonManifest {
+    images: Vec<ManifestRow>,
+}
+
+/// Manifest of the images exported by [`convert`](crate::convert).
+/// It is kept in memory and written to the sink by [`finish`](Self::finish).
+///
+/// With `resume`, it is also written whenever a file is completed, so that the images
+/// of the files that the next run skips are listed even if this run is interrupted.
+pub(crate) struct OutputManifest<'s> {
+    path: PathBuf,
+    format: ManifestFormat,
+    versions: BTreeMap<&'static str, String>,
+    // rows of the previous runs, see `create`
+    previous: Vec<ManifestRow>,
+    rows: Vec<ManifestRow>,
+    // rows of the exported files whose slices may still be saved, by target directory
+    unsaved: HashMap<PathBuf, Vec<ManifestRow>>,
+    resume: bool,
+    sink: &'s dyn OutputSink,
+}
+
+impl<'s> OutputManifest<'s> {
+    /// With `resume`, the rows of the previous manifest are kept, except for the directories
+    /// that are exported again
+    pub(crate) fn create(
+        py: Python<'_>,
+        root: &TargetImageDir,
+        format: ManifestFormat,
+        resume: bool,
+        sink: &'s dyn OutputSink,
+    ) -> Result<Self, ErrorTy> {
+        let versions = package_versions(py);
+        let path = match format {
+            ManifestFormat::Csv => {
+                let path = root.path.join("versions.json");
+                let bytes = serde_json::to_vec_pretty(&versions)
+                    .map_err(|e| ManifestWriteFailed(e.into(), path.display().to_string()))?;
+                sink.write(&path, &bytes)?;
+                root.path.join("manifest.csv")
+            }
+            ManifestFormat::Json => root.path.join("manifest.json"),
+        };
+        let previous = match resume {
+            true => read_previous(&path, format)?,
+            false => Vec::new(),
+        };
+        Ok(Self {
+            path,
+            format,
+            versions,
+            previous,
+            rows: Vec::new(),
+            unsaved: HashMap::new(),
+            resume,
+            sink,
+        })
+    }
+
+    /// Holds the images of an exported file until all its slices are saved,
+    /// see [`complete`](Self::complete) and [`discard`](Self::discard)
+    pub(crate) fn add_rows(&mut self, target_dir: &Path, rows: &mut Vec<ManifestRow>) {
+        self.unsaved
+            .entry(target_dir.to_path_buf())
+            .or_default()
+            .append(rows);
+    }
+
+    /// Lists the images of the file exported to `target_dir`, whose slices are all saved
+    pub(crate) fn complete(&mut self, target_dir: &Path) -> Result<(), ErrorTy> {
+        if let Some(mut rows) = self.unsaved.remove(target_dir) {
+            self.rows.append(&mut rows);
+        }
+        match self.resume {
+            true => self.write(),
+            false => Ok(()),
+        }
+    }
+
+    /// Drops the images of the file exported to `target_dir`, some of whose slices failed to save
+    pub(crate) fn discard(&mut self, target_dir: &Path) {
+        self.unsaved.remove(target_dir);
+    }
+
+    pub(crate) fn finish(self) -> Result<(), ErrorTy> {
+        self.write()
+    }
+
+    fn write(&self) -> Result<(), ErrorTy> {
+        let write_failed =
+            |e: std::io::Error| ManifestWriteFailed(e, self.path.display().to_string());
+        let exported: HashSet<&Path> = self
+            .rows
+            .iter()
+            .filter_map(|row| Path::new(&row.path).parent())
+            .collect();
+        let rows: Vec<&ManifestRow> = self
+            .previous
+            .iter()
+            .filter(|row| {
+                Path::new(&row.path)
+                    .parent()
+                    .is_none_or(|dir| !exported.contains(dir))
+            })
+            .chain(self.rows.iter())
+            .collect();
+        let bytes = match self.format {
+            ManifestFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                for row in rows {
+                    writer.serialize(row).map_err(|e| write_failed(e.into()))?;
+                }
+                writer
+                    .into_inner()
+                    .map_err(|e| write_failed(e.into_error()))?
+            }
+            ManifestFormat::Json => serde_json::to_vec(&JsonManifest {
+                versions: &self.versions,
+                images: rows,
+            })
+            .map_err(|e| write_failed(e.into()))?,
+        };
+        self.sink.write(&self.path, &bytes)
+    }
+}
+
+/// Rows of the manifest written by the previous run, if any
+fn read_previous(path: &Path, format: ManifestFormat) -> Result<Vec<ManifestRow>, ErrorTy> {
+    let read_failed = |e: std::io::Error| ManifestReadFailed(e, path.display().to_string());
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(read_failed(e)),
+    };
+    match format {
+        ManifestFormat::Csv => csv::Reader::from_reader(bytes.as_slice())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|e| read_failed(e.into())),
+        ManifestFormat::Json => serde_json::from_slice::<PreviousJsonManifest>(&bytes)
+            .map(|manifest| manifest.images)
+            .map_err(|e| read_failed(e.into())),
+    }
+}
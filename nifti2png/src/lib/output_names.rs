@@ -0,0 +1,168 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error_ty::ErrorTy, input_order::InputOrder, volume_format::VolumeFormat};
+
+/// What [`convert`](crate::convert) does when several entries of `nii_files` share the name
+/// of their directory in `png_stub`, e.g. `a.nii` and `a.nii.gz`, or `a.nii` and the DICOM folder `a`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NameCollision {
+    /// All the entries sharing the name fail to convert with `ErrorTy::OutputNameCollision`
+    #[default]
+    Error,
+    /// The first of the entries in the natural order keeps the name,
+    /// the others get the first free suffix of `-2`, `-3` and so on
+    Suffix,
+}
+
+/// Name of the directory of the entry: the file name without the extension of the format
+/// or the name of the folder. `None` for the entries that aren't converted.
+fn base_name(nii_files: &Path, entry: &OsStr) -> Option<OsString> {
+    match nii_files.join(entry).is_dir() {
+        true => Some(entry.to_os_string()),
+        false => VolumeFormat::stem(entry),
+    }
+}
+
+/// Names of the directories in `png_stub` for the entries of `nii_files`.
+///
+/// They are decided for the whole directory, so converting a part of the entries
+/// (e.g. by a worker process) gives them the same names as converting all of them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct OutputNames {
+    // entry -> name, or the entries sharing the name with `NameCollision::Error`
+    names: HashMap<OsString, Result<OsString, Vec<OsString>>>,
+}
+
+impl OutputNames {
+    pub(crate) fn new(nii_files: &Path, mut listing: Vec<OsString>, policy: NameCollision) -> Self {
+        InputOrder::Natural.sort(nii_files, &mut listing);
+        let mut groups = BTreeMap::<OsString, Vec<OsString>>::new();
+        for entry in listing {
+            if let Some(name) = base_name(nii_files, &entry) {
+                groups.entry(name).or_default().push(entry);
+            }
+        }
+
+        let mut taken: HashSet<OsString> = groups.keys().cloned().collect();
+        let mut names = HashMap::new();
+        for (name, entries) in groups {
+            if entries.len() == 1 || policy == NameCollision::Error {
+                let res = match entries.len() {
+                    1 => Ok(name),
+                    _ => Err(entries.clone()),
+                };
+                for entry in entries {
+                    names.insert(entry, res.clone());
+                }
+                continue;
+            }
+            let mut entries = entries.into_iter();
+            names.insert(
+                entries.next().expect("groups aren't empty"),
+                Ok(name.clone()),
+            );
+            let mut suffixes = (2..).map(|n| {
+                let mut suffixed = name.clone();
+                suffixed.push(format!("-{n}"));
+                suffixed
+            });
+            for entry in entries {
+                let suffixed = suffixes
+                    .find(|suffixed| !taken.contains(suffixed))
+                    .expect("the suffixes are endless");
+                tracing::info!(
+                    entry = %Path::new(&entry).display(),
+                    name = %Path::new(&suffixed).display(),
+                    "Renamed the output to avoid a name collision"
+                );
+                taken.insert(suffixed.clone());
+                names.insert(entry, Ok(suffixed));
+            }
+        }
+        Self { names }
+    }
+
+    /// The name resolved for a single entry, e.g. by the parent of a worker process
+    pub(crate) fn single(entry: OsString, name: OsString) -> Self {
+        Self {
+            names: HashMap::from([(entry, Ok(name))]),
+        }
+    }
+
+    /// Name of the directory of the entry. The entries that weren't listed,
+    /// such as the nested paths, get their [base name](base_name).
+    pub(crate) fn get(&self, nii_files: &Path, entry: &OsStr) -> Result<OsString, ErrorTy> {
+        match self.names.get(entry) {
+            Some(Ok(name)) => Ok(name.clone()),
+            Some(Err(entries)) => Err(ErrorTy::OutputNameCollision(
+                entries
+                    .iter()
+                    .map(|entry| Path::new(entry).display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )),
+            None => Ok(base_name(nii_files, entry).unwrap_or_else(|| entry.to_os_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `nii_files` with the DICOM folder `a` and the listing of the files next to it
+    fn listing(test: &str) -> (std::path::PathBuf, Vec<OsString>) {
+        let nii_files = std::env::temp_dir().join(format!(
+            "nifti2png-output-names-{test}-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(nii_files.join("a")).unwrap();
+        let listing = ["a.nii.gz", "b.nii", "a", "a-2.nii", "a.nii", "notes.txt"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        (nii_files, listing)
+    }
+
+    fn name(names: &OutputNames, nii_files: &Path, entry: &str) -> OsString {
+        names.get(nii_files, entry.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn suffixes_skip_the_taken_names() {
+        let (nii_files, listing) = listing("suffix");
+        let names = OutputNames::new(&nii_files, listing, NameCollision::Suffix);
+        // in the natural order: `a`, `a.nii`, `a.nii.gz`
+        assert_eq!(name(&names, &nii_files, "a"), "a");
+        assert_eq!(name(&names, &nii_files, "a.nii"), "a-3");
+        assert_eq!(name(&names, &nii_files, "a.nii.gz"), "a-4");
+        assert_eq!(name(&names, &nii_files, "a-2.nii"), "a-2");
+        assert_eq!(name(&names, &nii_files, "b.nii"), "b");
+        std::fs::remove_dir_all(nii_files).unwrap();
+    }
+
+    #[test]
+    fn collisions_fail_all_the_entries() {
+        let (nii_files, listing) = listing("error");
+        let names = OutputNames::new(&nii_files, listing, NameCollision::Error);
+        for entry in ["a", "a.nii", "a.nii.gz"] {
+            match names.get(&nii_files, entry.as_ref()) {
+                Err(ErrorTy::OutputNameCollision(entries)) => {
+                    assert_eq!(entries, "a, a.nii, a.nii.gz")
+                }
+                res => panic!("{entry}: {res:?}"),
+            }
+        }
+        assert_eq!(name(&names, &nii_files, "b.nii"), "b");
+        // not listed
+        assert_eq!(name(&names, &nii_files, "c.mgz"), "c");
+        assert_eq!(name(&names, &nii_files, "notes.txt"), "notes.txt");
+        std::fs::remove_dir_all(nii_files).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{create_dir_all, remove_dir_all, remove_file, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    target_path::TargetFile,
+    OutputSink,
+};
+
+/// Format of the [`ArchiveSink`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// Deflate-compressed zip
+    Zip,
+    /// Uncompressed tar, PNGs hardly compress anyway
+    Tar,
+}
+
+enum Writer {
+    Zip(Box<zip::ZipWriter<BufWriter<File>>>),
+    Tar(tar::Builder<BufWriter<File>>),
+}
+
+/// Single archive with all exported files.
+///
+/// Entries of an archive can't be replaced, so the files are staged in a hidden sibling directory
+/// and archived by [`finish`](OutputSink::finish), once per path with the last written content.
+/// The archive is written to a temporary sibling and renamed into place,
+/// so an interrupted conversion leaves no archive behind.
+pub struct ArchiveSink {
+    path: PathBuf,
+    temp: PathBuf,
+    staging: PathBuf,
+    format: ArchiveFormat,
+    // The staged file of every written path, `None` once finished
+    staged: Mutex<Option<BTreeMap<PathBuf, PathBuf>>>,
+}
+
+impl fmt::Debug for ArchiveSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveSink")
+            .field("path", &self.path)
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ArchiveSink {
+    pub fn create(path: impl Into<PathBuf>, format: ArchiveFormat) -> Result<Self, ErrorTy> {
+        let path = path.into();
+        let temp = TargetFile(path.clone()).temp_sibling();
+        let mut staging = temp.clone().into_os_string();
+        staging.push(".d");
+        let staging = PathBuf::from(staging);
+        // Left behind by an interrupted conversion of a process with the same id
+        if staging.exists() {
+            remove_dir_all(&staging)
+                .map_err(|e| RemoveDirAllFailed(e, staging.to_string_lossy().into_owned()))?;
+        }
+        create_dir_all(&staging)
+            .map_err(|e| CreateDirAllFailed(e, staging.to_string_lossy().into_owned()))?;
+        Ok(Self {
+            path,
+            temp,
+            staging,
+            format,
+            staged: Mutex::new(Some(BTreeMap::new())),
+        })
+    }
+
+    fn write_failed(&self, e: impl Into<std::io::Error>) -> ErrorTy {
+        SinkWriteFailed(e.into(), self.path.to_string_lossy().into_owned())
+    }
+
+    /// Archives the staged files to `temp`
+    fn archive(&self, staged: &BTreeMap<PathBuf, PathBuf>) -> Result<(), ErrorTy> {
+        let file = BufWriter::new(TargetFile(self.temp.clone()).create()?);
+        let mut writer = match self.format {
+            ArchiveFormat::Zip => Writer::Zip(Box::new(zip::ZipWriter::new(file))),
+            ArchiveFormat::Tar => Writer::Tar(tar::Builder::new(file)),
+        };
+        for (path, staged) in staged {
+            let bytes = std::fs::read(staged).map_err(|e| self.write_failed(e))?;
+            match &mut writer {
+                Writer::Zip(zip) => {
+                    let options = zip::write::FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated);
+                    zip.start_file(path.to_string_lossy(), options)
+                        .map_err(|e| self.write_failed(e))?;
+                    zip.write_all(&bytes).map_err(|e| self.write_failed(e))?;
+                }
+                Writer::Tar(tar) => {
+                    let mut header = tar::Header::new_gnu();
+                    header.set_size(bytes.len() as u64);
+                    header.set_mode(0o644);
+                    header.set_mtime(
+                        SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |mtime| mtime.as_secs()),
+                    );
+                    tar.append_data(&mut header, path, bytes.as_slice())
+                        .map_err(|e| self.write_failed(e))?;
+                }
+            }
+        }
+        let file = match writer {
+            Writer::Zip(mut zip) => zip.finish().map_err(|e| self.write_failed(e))?,
+            Writer::Tar(tar) => tar.into_inner().map_err(|e| self.write_failed(e))?,
+        };
+        file.into_inner()
+            .map_err(|e| self.write_failed(e.into_error()))?
+            .sync_all()
+            .map_err(|e| self.write_failed(e))
+    }
+}
+
+impl OutputSink for ArchiveSink {
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<(), ErrorTy> {
+        let mut staged = self.staged.lock().map_err(|_| EncoderThreadPanicked)?;
+        let Some(staged) = staged.as_mut() else {
+            return Err(self.write_failed(std::io::Error::other("the archive is already finished")));
+        };
+        let next = self.staging.join(staged.len().to_string());
+        let staged_path = staged.entry(path.to_path_buf()).or_insert(next);
+        std::fs::write(staged_path, bytes).map_err(|e| self.write_failed(e))
+    }
+
+    fn finish(&self) -> Result<(), ErrorTy> {
+        let Some(staged) = self
+            .staged
+            .lock()
+            .map_err(|_| EncoderThreadPanicked)?
+            .take()
+        else {
+            return Ok(());
+        };
+        let res = self.archive(&staged);
+        let _ = remove_dir_all(&self.staging);
+        match res {
+            Ok(()) => TargetFile(self.path.clone()).replace_with(&self.temp),
+            Err(e) => {
+                let _ = remove_file(&self.temp);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for ArchiveSink {
+    fn drop(&mut self) {
+        // Nothing to clean up once finished
+        if let Ok(None) = self.staged.get_mut().map(|staged| staged.as_ref()) {
+            return;
+        }
+        let _ = remove_dir_all(&self.staging);
+    }
+}
@@ -0,0 +1,50 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{error_ty::ErrorTy, OutputSink};
+
+/// Keeps the files in memory, e.g. for tests and services that send them elsewhere.
+///
+/// Clones share the files, so a clone can be passed to [`convert`](crate::convert)
+/// and the files taken from the original afterwards.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySink {
+    files: Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths of the files written so far, in order
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.lock().keys().cloned().collect()
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.lock().get(path.as_ref()).cloned()
+    }
+
+    /// Takes all files written so far out of the sink
+    pub fn take(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Vec<u8>>> {
+        // The map stays consistent even if a writer panicked
+        self.files
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<(), ErrorTy> {
+        self.lock().insert(path.to_path_buf(), bytes.to_vec());
+        Ok(())
+    }
+}
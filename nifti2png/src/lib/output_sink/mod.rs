@@ -0,0 +1,87 @@
+use std::{fmt, path::Path};
+
+mod archive_sink;
+mod memory_sink;
+mod s3_sink;
+pub use archive_sink::{ArchiveFormat, ArchiveSink};
+pub use memory_sink::MemorySink;
+pub use s3_sink::S3Sink;
+
+use crate::{error_ty::ErrorTy, target_path::TargetFile};
+
+/// Destination of the files exported by [`convert`](crate::convert),
+/// see [`ConvertOptionsBuilder::output_sink`](crate::ConvertOptionsBuilder::output_sink).
+///
+/// The paths are the same for every sink: `png_stub` followed by the directory
+/// of the volume and the name of the file, e.g. `slice/brain/0000.png`.
+/// The files are written from several threads with [`ExportMode::Parallel`](crate::ExportMode::Parallel).
+pub trait OutputSink: fmt::Debug + Send + Sync {
+    /// Writes the whole file. A file that is written twice is replaced.
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<(), ErrorTy>;
+
+    /// Called once after all files are written
+    fn finish(&self) -> Result<(), ErrorTy> {
+        Ok(())
+    }
+
+    /// Whether the paths are local directories, in which case the existing ones are handled
+    /// according to the [`OverwritePolicy`](crate::OverwritePolicy)
+    /// and [`resume`](crate::ConvertOptionsBuilder::resume) is supported
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// Plain directories on the local filesystem. Every file is written to a temporary sibling
+/// and renamed into place.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DirectorySink;
+
+impl OutputSink for DirectorySink {
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<(), ErrorTy> {
+        TargetFile(path.to_path_buf()).write_atomically(|temp| {
+            std::fs::write(temp, bytes)
+                .map_err(|e| ErrorTy::SinkWriteFailed(e, temp.to_string_lossy().into_owned()))
+        })
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn directory_sink_replaces_files() {
+        let dir = std::env::temp_dir().join(format!("nifti2png-dir-sink-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("brain")).unwrap();
+        let path = dir.join("brain/0000.png");
+        DirectorySink.write(&path, b"first").unwrap();
+        DirectorySink.write(&path, b"last").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"last");
+        // no temporary siblings left behind
+        assert_eq!(std::fs::read_dir(dir.join("brain")).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn memory_sink_clones_share_the_files() {
+        let sink = MemorySink::new();
+        let clone: Arc<dyn OutputSink> = Arc::new(sink.clone());
+        clone.write(Path::new("brain/0001.png"), b"other").unwrap();
+        clone.write(Path::new("brain/0000.png"), b"first").unwrap();
+        clone.write(Path::new("brain/0000.png"), b"last").unwrap();
+        assert_eq!(
+            sink.paths(),
+            [Path::new("brain/0000.png"), Path::new("brain/0001.png")]
+        );
+        assert_eq!(sink.get("brain/0000.png").unwrap(), b"last");
+        assert_eq!(sink.take().len(), 2);
+        assert!(sink.paths().is_empty());
+    }
+}
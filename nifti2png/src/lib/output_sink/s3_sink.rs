@@ -0,0 +1,151 @@
+use std::{fmt, path::Path};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{error_ty::ErrorTy, OutputSink};
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but the unreserved characters and, unless `segment`, the slashes
+fn uri_encode(s: &str, segment: bool) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !segment => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Bucket of Amazon S3 or a compatible object store, such as MinIO.
+///
+/// Every file is uploaded with a single `PUT` to the path-style URL `<endpoint>/<bucket>/<key>`,
+/// where the key is the path of the file. The requests are signed with AWS Signature Version 4.
+pub struct S3Sink {
+    // e.g. `https://s3.eu-central-1.amazonaws.com` or `http://127.0.0.1:9000`
+    endpoint: String,
+    // `host[:port]` of the endpoint, as signed
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    agent: ureq::Agent,
+}
+
+impl fmt::Debug for S3Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Sink")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Sink {
+    pub fn new(
+        endpoint: impl Into<String>,
+        bucket: impl Into<String>,
+        region: impl Into<String>,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        let endpoint = endpoint.into().trim_end_matches('/').to_string();
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint.as_str(), |(_scheme, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            endpoint,
+            host,
+            bucket: bucket.into(),
+            region: region.into(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    /// `Authorization` header of the `PUT` request
+    fn authorization(
+        &self,
+        uri: &str,
+        payload_hash: &str,
+        now: OffsetDateTime,
+    ) -> (String, String) {
+        let amz_date = now
+            .format(format_description!(
+                "[year][month][day]T[hour][minute][second]Z"
+            ))
+            .expect("the format has no optional components");
+        let date = &amz_date[..8];
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{uri}\n\nhost:{}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+            self.host
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = ["s3", "aws4_request"].into_iter().fold(
+            hmac(
+                &hmac(format!("AWS4{}", self.secret_key).as_bytes(), date),
+                &self.region,
+            ),
+            |key, data| hmac(&key, data),
+        );
+        let signature = hex::encode(hmac(&signing_key, &string_to_sign));
+        (
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.access_key
+            ),
+            amz_date,
+        )
+    }
+}
+
+impl OutputSink for S3Sink {
+    fn write(&self, path: &Path, bytes: &[u8]) -> Result<(), ErrorTy> {
+        let key = path.to_string_lossy();
+        let uri = format!(
+            "/{}/{}",
+            uri_encode(&self.bucket, true),
+            uri_encode(key.trim_start_matches('/'), false)
+        );
+        let payload_hash = hex::encode(Sha256::digest(bytes));
+        let (authorization, amz_date) =
+            self.authorization(&uri, &payload_hash, OffsetDateTime::now_utc());
+        self.agent
+            .put(&format!("{}{uri}", self.endpoint))
+            .set("Authorization", &authorization)
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("x-amz-date", &amz_date)
+            .send_bytes(bytes)
+            .map(drop)
+            .map_err(|e| {
+                ErrorTy::SinkWriteFailed(
+                    std::io::Error::other(e),
+                    format!("s3://{}/{key}", self.bucket),
+                )
+            })
+    }
+}
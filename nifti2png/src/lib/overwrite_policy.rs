@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// What [`convert`](crate::convert) does when the directory for the slices of a volume already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverwritePolicy {
+    /// The volume fails to convert with `ErrorTy::TargetExists`, unless the directory is empty
+    #[default]
+    Error,
+    /// The volume is not converted and is listed in [`ConvertReport::skipped`](crate::ConvertReport::skipped)
+    /// if all its slices were exported. The directories of interrupted exports are overwritten.
+    SkipExisting,
+    /// The directory is removed along with the previous export and created again
+    Overwrite,
+    /// The slices are written to the first free sibling directory: `<dir>-v2`, `<dir>-v3` and so on
+    Versioned,
+}
@@ -0,0 +1,56 @@
+//! Conversions that keep the paths which aren't valid UTF-8, such as Latin-1 file names, intact
+
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+use pyo3::prelude::*;
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// `pathlib.Path` of the path. PyO3 decodes it the same way as `os.fsdecode`,
+/// and the Python packages encode it back to the original bytes.
+pub(crate) fn py_path<'py>(py: Python<'py>, path: &Path) -> PyResult<&'py PyAny> {
+    py.import("pathlib")?.getattr("Path")?.call1((path,))
+}
+
+/// Bytes of the path for the text files and pipes. Lossy outside of Unix.
+#[cfg(unix)]
+pub(crate) fn os_to_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(s.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn os_to_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Owned(s.to_string_lossy().into_owned().into_bytes())
+}
+
+/// The inverse of [`os_to_bytes`]
+#[cfg(unix)]
+pub(crate) fn os_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn os_from_bytes(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// `#[serde(with = "path_bytes")]` for the paths that go through [`os_to_bytes`],
+/// since serde rejects the paths which aren't UTF-8
+pub(crate) mod path_bytes {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&os_to_bytes(path.as_os_str()))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PathBuf, D::Error> {
+        Vec::deserialize(deserializer).map(|bytes| os_from_bytes(bytes).into())
+    }
+}
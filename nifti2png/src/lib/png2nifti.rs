@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use pyo3::{prelude::*, types::PyBytes};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    orientation::voxel2pixel,
+    paths::py_path,
+};
+
+/// Assembles a directory of `{z:04}.png` slices produced by [`convert`](crate::convert)
+/// back into a NIFTI volume with the affine and the header of the `reference` image.
+///
+/// The voxels are read from the first channel of the slices and stored as `uint8`,
+/// which makes the function suitable for masks drawn on top of the exported slices.
+/// The voxels that `convert` crops away from non-square slices are set to 0.
+#[tracing::instrument(skip_all, fields(
+    png_dir = %png_dir.as_ref().display(),
+    reference = %reference.as_ref().display(),
+    output = %output.as_ref().display(),
+))]
+pub fn png2nifti(
+    png_dir: impl AsRef<Path>,
+    reference: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<(), ErrorTy> {
+    let (png_dir, reference, output) = (png_dir.as_ref(), reference.as_ref(), output.as_ref());
+    Python::with_gil(|py| {
+        let nib = py.import("nibabel").map_err(MissingThirdPartyLibrary)?;
+        let np = py.import("numpy").map_err(MissingThirdPartyLibrary)?;
+
+        let ref_obj = nib.call_method1("load", (py_path(py, reference)?,))?;
+        let hdr = ref_obj.getattr("header")?;
+        let ref_shape = hdr.call_method0("get_data_shape")?;
+        let [height, width, depth] = match ref_shape.len()? {
+            3 | 4 => {
+                let mut dims = [0usize; 3];
+                for (i, dim) in dims.iter_mut().enumerate() {
+                    *dim = ref_shape.get_item(i)?.extract::<usize>()?;
+                }
+                dims
+            }
+            len => {
+                return Err(UnsupportedDimensionality(
+                    len,
+                    reference.display().to_string(),
+                ))
+            }
+        };
+
+        // C-ordered (height, width, depth) array
+        let mut voxels = vec![0u8; height * width * depth];
+        for z in 0..depth {
+            let png_path = png_dir.join(format!("{z:04}.png"));
+            let png = image::open(&png_path)
+                .map_err(|e| ImageOpenFailed(e, png_path.to_string_lossy().into_owned()))?
+                .to_luma8();
+            if png.dimensions() != (width as u32, height as u32) {
+                return Err(PngDimensionsMismatch(
+                    png_path.to_string_lossy().into_owned(),
+                    png.dimensions(),
+                    (width as u32, height as u32),
+                ));
+            }
+            for row in 0..height {
+                for col in 0..width {
+                    if let Some([x, y]) = voxel2pixel(height, width, [row, col]) {
+                        voxels[(row * width + col) * depth + z] =
+                            png.get_pixel(x as u32, y as u32)[0];
+                    }
+                }
+            }
+        }
+
+        let data = np
+            .call_method1("frombuffer", (PyBytes::new(py, &voxels), "uint8"))?
+            .call_method1("reshape", ((height, width, depth),))?;
+        // The class of the reference is kept, e.g. NIfTI-2 for dimensions that don't fit into i16
+        let nii_obj = ref_obj
+            .get_type()
+            .call1((data, ref_obj.getattr("affine")?, hdr))?;
+        nii_obj.call_method1("set_data_dtype", ("uint8",))?;
+        nib.call_method1("save", (nii_obj, py_path(py, output)?))?;
+        Ok(())
+    })
+}
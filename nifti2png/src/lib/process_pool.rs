@@ -0,0 +1,440 @@
+//! Conversion of a directory split across several worker processes.
+//!
+//! nibabel loading and skimage rescaling hold the GIL, so a single process can't convert
+//! several volumes at once. [`convert_in_processes`] starts the current executable again
+//! as a number of workers, each with its own embedded interpreter, and hands them
+//! the entries of the directory one by one.
+//!
+//! Workers talk to the parent through their stdin and stdout:
+//!
+//! - the parent writes the [`ConvertOptions`] as a single line of JSON and then every entry
+//!   followed by the name of its directory, so that the workers don't list the directory again.
+//!   The entries are written as length-prefixed frames, as they may contain any bytes,
+//!   newlines included;
+//! - the worker answers every entry with a line starting with `MESSAGE_PREFIX`: `ok`, `skipped`
+//!   or `err` followed by the reason;
+//! - every other line printed by the worker is logged by the parent.
+//!
+//! The workers report nothing but these answers, so the observer of
+//! [`convert_in_processes_with_observer`] gets the progress of the entries, not of the slices.
+
+use std::{
+    collections::VecDeque,
+    ffi::{OsStr, OsString},
+    io::{BufRead, BufReader, Lines, Write},
+    num::NonZeroUsize,
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    },
+};
+
+use pyo3::prelude::*;
+
+use crate::{
+    convert_with_observer,
+    error_ty::ErrorTy::{self, *},
+    output_names::OutputNames,
+    paths::{os_from_bytes, os_to_bytes},
+    rel_nii_files_iter::{entries_to_convert, list_entries},
+    ConsoleObserver, ConvertObserver, ConvertOptions, ConvertReport, SilentObserver,
+};
+
+/// Environment variable that turns the process into a conversion worker,
+/// see [`run_worker_if_requested`].
+pub const WORKER_ENV_VAR: &str = "NIFTI2PNG_WORKER";
+
+/// Prefix of the lines with the results of the conversion of the entries
+const MESSAGE_PREFIX: &str = "nifti2png-worker:";
+
+/// Results of [`convert_in_processes`]
+#[derive(Debug, Default)]
+pub struct ProcessPoolReport {
+    /// Entries that were converted successfully
+    pub converted: Vec<OsString>,
+    /// Entries whose directories already existed with [`OverwritePolicy::SkipExisting`](crate::OverwritePolicy::SkipExisting)
+    /// or that were converted by a previous run with [`resume`](crate::ConvertOptionsBuilder::resume)
+    pub skipped: Vec<OsString>,
+    /// Entries that failed along with the reason
+    pub failed: Vec<(OsString, String)>,
+}
+
+impl ProcessPoolReport {
+    /// The same report with the paths of the entries, for [`ConvertObserver::finished`]
+    fn to_convert_report(&self, nii_files: &Path) -> ConvertReport {
+        let sources = |entries: &[OsString]| entries.iter().map(|e| nii_files.join(e)).collect();
+        ConvertReport::of_sources(
+            sources(&self.converted),
+            sources(&self.skipped),
+            self.failed
+                .iter()
+                .map(|(entry, e)| (nii_files.join(entry), WorkerFailed(e.clone())))
+                .collect(),
+        )
+    }
+}
+
+/// Writes the length of the raw bytes of the path, which doesn't have to be UTF-8, and the bytes
+fn write_frame(w: &mut impl Write, s: &OsStr) -> std::io::Result<()> {
+    let bytes = os_to_bytes(s);
+    w.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    w.write_all(&bytes)
+}
+
+/// The inverse of [`write_frame`]. `None` at the end of the input.
+fn read_frame(r: &mut impl BufRead) -> std::io::Result<Option<OsString>> {
+    let mut len = [0; 8];
+    match r.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut bytes = vec![0; u64::from_le_bytes(len) as usize];
+    r.read_exact(&mut bytes)?;
+    Ok(Some(os_from_bytes(bytes)))
+}
+
+/// Writes the options, but the entries and the sink, as a single line of JSON
+fn write_header(options: &ConvertOptions, w: &mut impl Write) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *w, options)?;
+    writeln!(w)
+}
+
+/// The inverse of [`write_header`]
+fn read_header(r: &mut impl BufRead) -> Result<ConvertOptions, ErrorTy> {
+    let mut line = String::new();
+    r.read_line(&mut line).map_err(ReadJobFailed)?;
+    serde_json::from_str(&line).map_err(InvalidJobHeader)
+}
+
+/// The next entry and the name of its directory. `None` at the end of the input.
+fn read_entry(r: &mut impl BufRead) -> Result<Option<(OsString, OsString)>, ErrorTy> {
+    let Some(entry) = read_frame(r).map_err(ReadJobFailed)? else {
+        return Ok(None);
+    };
+    match read_frame(r).map_err(ReadJobFailed)? {
+        Some(name) => Ok(Some((entry, name))),
+        None => Err(ReadJobFailed(std::io::ErrorKind::UnexpectedEof.into())),
+    }
+}
+
+/// What the worker answers for the converted entry: `ok`, `skipped`
+/// if it was skipped by `resume` or the overwrite policy, or the failures
+fn answer(report: &ConvertReport) -> Result<&'static str, String> {
+    if !report.failed.is_empty() {
+        let failures: Vec<_> = report.failed.iter().map(|(_, e)| e.to_string()).collect();
+        return Err(failures.join("; "));
+    }
+    match report.converted.is_empty() && !report.skipped.is_empty() {
+        true => Ok("skipped"),
+        false => Ok("ok"),
+    }
+}
+
+/// Runs the worker loop and exits the process if [`WORKER_ENV_VAR`] is set.
+///
+/// Executables that call [`convert_in_processes`] must call this function first thing in `main`.
+pub fn run_worker_if_requested() {
+    if std::env::var_os(WORKER_ENV_VAR).is_none() {
+        return;
+    }
+    let mut stdin = std::io::stdin().lock();
+    // An invalid header fails every entry, so that the parent reports why
+    let options = read_header(&mut stdin).map_err(|e| e.to_string());
+
+    loop {
+        let (entry, name) = match read_entry(&mut stdin) {
+            Ok(Some(job)) => job,
+            Ok(None) => break,
+            Err(e) => {
+                // The parent is out of sync with the worker, so it can't tell which entry failed
+                tracing::error!(error = %e, "The worker stopped");
+                std::process::exit(1);
+            }
+        };
+        let _entry_span = tracing::info_span!(
+            "worker",
+            pid = std::process::id(),
+            entry = %Path::new(&entry).display()
+        )
+        .entered();
+        let res = options.clone().and_then(|options| {
+            options
+                .into_builder()
+                .entries(vec![entry.clone()])
+                .output_names(OutputNames::single(entry, name))
+                .build()
+                .and_then(|options| convert_with_observer(&options, &mut SilentObserver))
+                .map_err(|e| e.to_string())
+                .and_then(|report| answer(&report))
+        });
+        match res {
+            Ok(answer) => println!("{MESSAGE_PREFIX}{answer}"),
+            Err(e) => println!("{MESSAGE_PREFIX}err {}", e.replace('\n', " ")),
+        }
+    }
+    std::process::exit(0);
+}
+
+/// Result of an entry as answered by the worker
+enum Outcome {
+    Converted,
+    Skipped,
+    Failed(String),
+}
+
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl Worker {
+    fn spawn(options: &ConvertOptions) -> Result<Self, ErrorTy> {
+        let mut child = std::env::current_exe()
+            .and_then(|exe| {
+                Command::new(exe)
+                    .env(WORKER_ENV_VAR, "1")
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+            })
+            .map_err(SpawnWorkerFailed)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+        write_header(options, &mut stdin).map_err(SpawnWorkerFailed)?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Returns `None` if the worker died before reporting the result.
+    /// The other lines printed by the worker are logged.
+    fn convert(&mut self, entry: &OsStr, name: &OsStr) -> Option<Outcome> {
+        write_frame(&mut self.stdin, entry).ok()?;
+        write_frame(&mut self.stdin, name).ok()?;
+        for line in self.stdout.by_ref() {
+            let line = line.ok()?;
+            match line.strip_prefix(MESSAGE_PREFIX) {
+                Some("ok") => return Some(Outcome::Converted),
+                Some("skipped") => return Some(Outcome::Skipped),
+                Some(msg) => {
+                    return Some(Outcome::Failed(msg.trim_start_matches("err ").to_string()))
+                }
+                None => tracing::debug!(output = line, "Output of the worker process"),
+            }
+        }
+        None
+    }
+
+    fn exit_status(self) -> String {
+        let Self {
+            mut child, stdin, ..
+        } = self;
+        drop(stdin);
+        match child.wait() {
+            Ok(status) => format!("The worker process crashed ({status})"),
+            Err(e) => format!("The worker process crashed: {e}"),
+        }
+    }
+
+    fn finish(self) {
+        let Self {
+            mut child, stdin, ..
+        } = self;
+        drop(stdin);
+        let _ = child.wait();
+    }
+}
+
+/// Feeds the entries from the queue to a worker and restarts it whenever it crashes.
+/// The outcomes of the entries are sent to the thread of the observer.
+fn drive_worker(
+    options: &ConvertOptions,
+    queue: &Mutex<VecDeque<(OsString, OsString)>>,
+    outcomes: Sender<(OsString, Outcome)>,
+) -> Result<(), ErrorTy> {
+    let mut worker: Option<Worker> = None;
+    loop {
+        let Some((entry, name)) = queue.lock().unwrap().pop_front() else {
+            break;
+        };
+        let mut running = match worker.take() {
+            Some(worker) => worker,
+            None => Worker::spawn(options)?,
+        };
+        let outcome = match running.convert(&entry, &name) {
+            Some(outcome) => {
+                worker = Some(running);
+                outcome
+            }
+            None => Outcome::Failed(running.exit_status()),
+        };
+        outcomes
+            .send((entry, outcome))
+            .expect("the outcomes are received until the drivers stop");
+    }
+    if let Some(worker) = worker {
+        worker.finish();
+    }
+    Ok(())
+}
+
+/// Same as [`convert`](crate::convert) but the entries of the directory are converted
+/// by `processes` worker processes, see the [module-level documentation](self).
+///
+/// A failing entry or a crashing worker doesn't stop the conversion of the other entries.
+/// The failures are collected in the [`ProcessPoolReport`].
+pub fn convert_in_processes(
+    options: &ConvertOptions,
+    processes: NonZeroUsize,
+) -> Result<ProcessPoolReport, ErrorTy> {
+    convert_in_processes_with_observer(options, processes, &mut ConsoleObserver)
+}
+
+/// Same as [`convert_in_processes`] but the progress is reported to `observer`.
+///
+/// The observer is called from the current thread with the progress of the entries,
+/// the entries that failed and the end of the conversion.
+/// The slices of the volumes are exported by the workers and aren't reported.
+pub fn convert_in_processes_with_observer(
+    options: &ConvertOptions,
+    processes: NonZeroUsize,
+    observer: &mut dyn ConvertObserver,
+) -> Result<ProcessPoolReport, ErrorTy> {
+    let res = convert_entries(options, processes, observer);
+    match &res {
+        Ok(report) => observer.finished(&report.to_convert_report(&options.nii_files)),
+        Err(e) => {
+            tracing::error!(error = %e, "The conversion failed");
+            observer.error(e)
+        }
+    };
+    res
+}
+
+fn convert_entries(
+    options: &ConvertOptions,
+    processes: NonZeroUsize,
+    observer: &mut dyn ConvertObserver,
+) -> Result<ProcessPoolReport, ErrorTy> {
+    if std::env::var_os(WORKER_ENV_VAR).is_some() {
+        // The workers would start the current executable again and again
+        return Err(UnsupportedInProcesses("`convert_in_processes`"));
+    }
+    if options.output_manifest.is_some() {
+        // Every worker would overwrite the manifest with the images of its last entry
+        return Err(UnsupportedInProcesses("`output_manifest`"));
+    }
+    if options.output_sink.is_some() {
+        // The sinks live in the memory of the parent
+        return Err(UnsupportedInProcesses("`output_sink`"));
+    }
+    // The names depend on all the entries, so they are resolved once for all workers
+    let (entries, names) = Python::with_gil(|py| {
+        let os = py.import("os").map_err(MissingStandardLibrary)?;
+        let listing = list_entries(os, options)?;
+        let entries = entries_to_convert(options, &listing);
+        Ok::<_, ErrorTy>((
+            entries,
+            OutputNames::new(&options.nii_files, listing, options.name_collision),
+        ))
+    })?;
+    let total = entries.len();
+    let mut queue = VecDeque::with_capacity(total);
+    let mut report = ProcessPoolReport::default();
+    for entry in entries {
+        match names.get(&options.nii_files, &entry) {
+            Ok(name) => queue.push_back((entry, name)),
+            Err(e) => {
+                tracing::warn!(entry = %Path::new(&entry).display(), error = %e, "Failed");
+                observer.file_failed(&options.nii_files.join(&entry), &e);
+                report.failed.push((entry, e.to_string()));
+            }
+        }
+    }
+    observer.entries_progress(report.failed.len(), total);
+    let queue = &Mutex::new(queue);
+    let (sender, outcomes) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        let drivers: Vec<_> = (0..processes.get())
+            .map(|_| {
+                let sender = sender.clone();
+                s.spawn(move || drive_worker(options, queue, sender))
+            })
+            .collect();
+        // The outcomes end once all the drivers stop
+        drop(sender);
+        for (entry, outcome) in outcomes {
+            let done = report.converted.len() + report.skipped.len() + report.failed.len() + 1;
+            match outcome {
+                Outcome::Converted => {
+                    tracing::info!(done, total, entry = %Path::new(&entry).display(), "Converted");
+                    report.converted.push(entry);
+                }
+                Outcome::Skipped => {
+                    tracing::info!(done, total, entry = %Path::new(&entry).display(), "Skipped");
+                    report.skipped.push(entry);
+                }
+                Outcome::Failed(e) => {
+                    tracing::warn!(done, total, entry = %Path::new(&entry).display(), error = e, "Failed");
+                    observer.file_failed(&options.nii_files.join(&entry), &WorkerFailed(e.clone()));
+                    report.failed.push((entry, e));
+                }
+            }
+            observer.entries_progress(done, total);
+        }
+        drivers
+            .into_iter()
+            .try_for_each(|driver| driver.join().expect("The worker driver panicked"))
+    })?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryBudget, OverBudget, OverwritePolicy, VolumeAccess};
+
+    #[test]
+    fn header_round_trip() {
+        let nii_files = os_from_bytes(b"scans-\xe9".to_vec());
+        let options = ConvertOptions::builder(&nii_files)
+            .png_stub("png")
+            .minmax(0, 4095)
+            .volume_access(VolumeAccess::Lazy)
+            .memory_budget(MemoryBudget {
+                bytes: 1 << 30,
+                on_exceed: OverBudget::Stream,
+            })
+            .resume(true)
+            .overwrite(OverwritePolicy::Overwrite)
+            .build()
+            .unwrap();
+        let mut header = Vec::new();
+        write_header(&options, &mut header).unwrap();
+        assert_eq!(header.iter().filter(|&&b| b == b'\n').count(), 1);
+
+        let received = read_header(&mut header.as_slice()).unwrap();
+        assert_eq!(received.nii_files.as_os_str(), nii_files);
+        assert_eq!(format!("{received:?}"), format!("{options:?}"));
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert!(matches!(
+            read_header(&mut b"resume\n".as_slice()),
+            Err(InvalidJobHeader(_))
+        ));
+        assert!(matches!(
+            read_header(&mut b"".as_slice()),
+            Err(InvalidJobHeader(_))
+        ));
+    }
+}
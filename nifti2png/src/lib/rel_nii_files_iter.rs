@@ -1,88 +1,276 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
 
 use crate::{
+    bids,
+    dicom_series::{find_dicom_series, DicomSeries},
     error_ty::ErrorTy::{self, *},
+    memory_budget::MemoryBudget,
+    output_names::OutputNames,
+    paths::py_path,
+    resume_manifest::{CompletedInputs, Fingerprint},
     target_path::TargetImageDir,
+    volume_format::VolumeFormat,
+    ConvertOptions,
 };
 use pyo3::{
     prelude::*,
-    types::PyIterator,
+    types::{PyIterator, PyList},
 };
 
-/// Iterator over pairs of (png_stub, nii_obj) for all nii files in nii_files
+/// All entries of nii_files in the order of the filesystem: the names in the directory or,
+/// with [`bids`](crate::ConvertOptionsBuilder::bids), the paths of the volumes relative to it
+pub(crate) fn list_entries(
+    os: &PyModule,
+    options: &ConvertOptions,
+) -> Result<Vec<OsString>, ErrorTy> {
+    let nii_files = options.nii_files.as_path();
+    if options.bids.is_some() {
+        return bids::discover(nii_files);
+    }
+    py_path(os.py(), nii_files)
+        .and_then(|nii_files| os.call_method1("listdir", (nii_files,)))
+        .and_then(|entries| entries.extract())
+        .map_err(|e| ListDirFailed(e, nii_files.display().to_string()))
+}
+
+/// Entries to convert: the listed ones that pass the BIDS filter, if any, in the `input_order`
+/// or the ones given explicitly
+pub(crate) fn entries_to_convert(options: &ConvertOptions, listing: &[OsString]) -> Vec<OsString> {
+    if let Some(entries) = &options.entries {
+        return entries.clone();
+    }
+    let mut entries: Vec<OsString> = match &options.bids {
+        Some(filter) => listing
+            .iter()
+            .filter(|entry| filter.accepts(entry))
+            .cloned()
+            .collect(),
+        None => listing.to_vec(),
+    };
+    options.input_order.sort(&options.nii_files, &mut entries);
+    entries
+}
+
+/// Iterator over triples of (png_stub, source, nii_obj) for all nii files in nii_files
 /// where png_stub is a path to a directory where the png files
-/// for the NIFTI volume will be saved and nii_obj is a NIFTI object (with a header and data)
+/// for the NIFTI volume will be saved, source is the path to the file (or the DICOM series folder)
+/// and nii_obj is a NIFTI object (with a header and data)
+///
+/// Only the files of the supported [`VolumeFormat`]s are loaded. Paired formats are loaded
+/// once, through their `.hdr`/`.PAR` file, and the files of unknown formats are skipped.
+///
+/// The slices of a file are saved to `png_stub/<file name without the extension>`.
+/// Subdirectories of nii_files are treated as DICOM series folders. Every series found there
+/// is assembled into a NIFTI object and saved to `png_stub/<subdirectory>/<SeriesInstanceUID>`.
+/// The names shared by several entries are resolved by [`OutputNames`].
 pub(crate) struct RelNiiFilesIter<'a>
 where
     Self: 'a,
 {
     nib: &'a PyModule,
     os: &'a PyModule,
-    nii_files: &'a str,
+    nii_files: PathBuf,
     base_png_stub: PathBuf,
+    names: OutputNames,
     listdir_iter: &'a PyIterator,
+    // number of the entries taken from `listdir_iter` and of all entries
+    consumed: usize,
+    total: usize,
+    // DICOM series of the last listed directory that haven't been assembled yet.
+    // They don't borrow Python objects, so they outlive the GIL pool of the entry where they were found.
+    pending: VecDeque<(TargetImageDir<'a>, PathBuf, DicomSeries)>,
+    // sources to skip, see `ConvertOptionsBuilder::resume`
+    completed: Option<CompletedInputs>,
+    // of the sources yielded with `completed`, by target directory
+    fingerprints: HashMap<PathBuf, Fingerprint>,
+    // sources skipped because of `completed` that haven't been taken yet
+    skipped: Vec<PathBuf>,
+    // checked before the DICOM series are assembled
+    memory_budget: Option<MemoryBudget>,
 }
 
 impl<'a> RelNiiFilesIter<'a> {
+    /// Uses `nii_files`, `entries`, `input_order`, `name_collision`, `bids`, `png_stub`,
+    /// `memory_budget` and `output_names` of the options
     pub(crate) fn new(
         nib: &'a PyModule,
         os: &'a PyModule,
-        nii_files: &'a str,
-        base_png_stub: PathBuf,
+        options: &ConvertOptions,
+        completed: Option<CompletedInputs>,
     ) -> Result<Self, ErrorTy> {
-        let listdir_iter = match os.call_method("listdir", (nii_files,), None) {
-            Ok(listdir_res) => listdir_res.iter()?,
-            Err(e) => return Err(ListDirFailed(e, nii_files.to_string())),
+        let nii_files = options.nii_files.as_path();
+        let (entries, names) = match (&options.entries, &options.output_names) {
+            (Some(entries), Some(names)) => (entries.clone(), names.clone()),
+            _ => {
+                // The names depend on all the entries, even if only some of them are converted
+                let listing = list_entries(os, options)?;
+                let entries = entries_to_convert(options, &listing);
+                (
+                    entries,
+                    OutputNames::new(nii_files, listing, options.name_collision),
+                )
+            }
         };
+        let total = entries.len();
+        let listdir_iter = PyIterator::from_object(os.py(), PyList::new(os.py(), entries))?;
         Ok(Self {
             nib,
             os,
-            nii_files,
+            nii_files: nii_files.to_path_buf(),
             listdir_iter,
-            base_png_stub,
+            consumed: 0,
+            total,
+            base_png_stub: options.png_stub.clone(),
+            names,
+            pending: VecDeque::new(),
+            completed,
+            fingerprints: HashMap::new(),
+            skipped: Vec::new(),
+            memory_budget: options.memory_budget,
         })
     }
 
+    /// Entries that are done and all entries, see [`ConvertObserver::entries_progress`](crate::ConvertObserver::entries_progress)
+    pub(crate) fn progress(&self) -> (usize, usize) {
+        // The folder of the pending DICOM series is still in progress
+        (
+            self.consumed - usize::from(!self.pending.is_empty()),
+            self.total,
+        )
+    }
+
+    /// DICOM series of the last listed folder that haven't been yielded yet
+    pub(crate) fn pending_series(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Sources skipped since the last call because a previous run has converted them
+    pub(crate) fn take_skipped(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.skipped)
+    }
+
+    /// Fingerprint of the source yielded for `target_dir`, taken before it was loaded.
+    /// `None` without [`resume`](crate::ConvertOptionsBuilder::resume)
+    /// or if the files of the source couldn't be read.
+    pub(crate) fn take_fingerprint(&mut self, target_dir: &Path) -> Option<Fingerprint> {
+        self.fingerprints.remove(target_dir)
+    }
+
+    fn is_completed(&mut self, png_stub: &TargetImageDir, source: &Path) -> bool {
+        let Some(completed) = &self.completed else {
+            return false;
+        };
+        let current = match completed.fingerprint(source) {
+            Ok(current) => current,
+            Err(e) => {
+                tracing::warn!(source = %source.display(), error = %e, "Failed to fingerprint the source");
+                return false;
+            }
+        };
+        if completed.contains(png_stub, &current) {
+            tracing::info!(source = %source.display(), "Skipping the source converted by a previous run");
+            self.skipped.push(source.to_path_buf());
+            return true;
+        }
+        self.fingerprints.insert(png_stub.path.clone(), current);
+        false
+    }
+
     fn png_stub(&self, nii_file: &PyAny) -> Result<TargetImageDir<'a>, ErrorTy> {
-        let nii_file = nii_file.extract::<String>()?;
-        // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L66
-        Ok(TargetImageDir(self.base_png_stub.join(nii_file)))
+        let nii_file = nii_file.extract::<OsString>()?;
+        // Unlike https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L66,
+        // the extension is stripped from the file name
+        let name = self.names.get(&self.nii_files, &nii_file)?;
+        Ok(TargetImageDir(self.base_png_stub.join(name)))
+    }
+
+    fn nii_path(&self, nii_file: &PyAny) -> Result<PathBuf, ErrorTy> {
+        // nii_files + "\\" + nii_file
+        Ok(self.nii_files.join(nii_file.extract::<OsString>()?))
     }
 
-    fn nii_obj(&self, nii_file: &PyAny) -> Result<&'a PyAny, ErrorTy> {
+    fn nii_obj(&self, source: &Path, format: VolumeFormat) -> Result<&'a PyAny, ErrorTy> {
         // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L75
-        let nii_obj = self.nib.call_method(
-            "load",
-            ({
-                // nii_files + "\\" + nii_file
-                self.os
-                    .getattr("path")?
-                    .call_method("join", (self.nii_files, nii_file), None)?
-            },),
-            None,
-        )?;
+        let nii_obj = format.load(self.nib, py_path(self.os.py(), source)?)?;
         Ok(nii_obj)
     }
 
+    fn find_dicom_series(&mut self, nii_file: &PyAny, dir: &Path) -> Result<(), ErrorTy> {
+        let png_stub = self.png_stub(nii_file)?;
+        for series in find_dicom_series(self.os.py(), self.os, dir)? {
+            let png_stub = TargetImageDir(png_stub.path.join(&series.uid));
+            self.pending
+                .push_back((png_stub, dir.to_path_buf(), series));
+        }
+        Ok(())
+    }
+
+    /// Returns `None` for skipped files and for DICOM series folders,
+    /// whose series are put into the `pending` queue.
+    ///
+    /// The errors of the entry are attributed to it with [`ErrorTy::FileFailed`].
     // TODO: improve naming
     fn item(
-        &self,
+        &mut self,
         nii_file: PyResult<&'a PyAny>,
     ) -> Result<
-        (
+        Option<(
             // png_stub
             TargetImageDir<'a>,
+            // source
+            PathBuf,
             // nii_obj
             &'a PyAny,
-        ),
+        )>,
         ErrorTy,
     > {
         let nii_file = nii_file?;
+        let source = self.nii_path(nii_file)?;
+        self.entry_item(nii_file, &source)
+            .map_err(|e| e.in_file(source))
+    }
+
+    fn entry_item(
+        &mut self,
+        nii_file: &'a PyAny,
+        source: &Path,
+    ) -> Result<
+        Option<(
+            // png_stub
+            TargetImageDir<'a>,
+            // source
+            PathBuf,
+            // nii_obj
+            &'a PyAny,
+        )>,
+        ErrorTy,
+    > {
+        let is_dir = self
+            .os
+            .getattr("path")?
+            .call_method1("isdir", (py_path(self.os.py(), source)?,))?
+            .extract::<bool>()?;
+        if is_dir {
+            self.find_dicom_series(nii_file, source)?;
+            return Ok(None);
+        }
+
+        let format = match VolumeFormat::detect(&nii_file.extract::<OsString>()?) {
+            Some(format) => format,
+            None => return Ok(None),
+        };
 
         let png_stub = self.png_stub(nii_file)?;
-        let nii_obj = self.nii_obj(nii_file)?;
+        if self.is_completed(&png_stub, source) {
+            return Ok(None);
+        }
+        let nii_obj = self.nii_obj(source, format)?;
 
-        Ok((png_stub, nii_obj))
+        Ok(Some((png_stub, source.to_path_buf(), nii_obj)))
     }
 }
 
@@ -91,6 +279,8 @@ impl<'a> Iterator for RelNiiFilesIter<'a> {
         (
             // png_stub
             TargetImageDir<'a>,
+            // source
+            PathBuf,
             // nii_obj
             &'a PyAny,
         ),
@@ -98,8 +288,25 @@ impl<'a> Iterator for RelNiiFilesIter<'a> {
     >;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.listdir_iter
-            .next()
-            .map(|nii_file_res| self.item(nii_file_res))
+        loop {
+            if let Some((png_stub, source, series)) = self.pending.pop_front() {
+                if self.is_completed(&png_stub, &source) {
+                    continue;
+                }
+                return Some(
+                    match series.into_nii_obj(self.os.py(), self.nib, self.memory_budget) {
+                        Ok(nii_obj) => Ok((png_stub, source, nii_obj)),
+                        Err(e) => Err(e.in_file(source)),
+                    },
+                );
+            }
+            let nii_file_res = self.listdir_iter.next()?;
+            self.consumed += 1;
+            match self.item(nii_file_res) {
+                Ok(Some(item)) => return Some(Ok(item)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
     }
 }
@@ -1,69 +1,190 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::{
-    error_ty::ErrorTy, nii_image::NiiImage, rel_nii_files_iter::RelNiiFilesIter,
+    error_ty::ErrorTy::{self, *},
+    memory_budget::{estimate_memory, BudgetDecision, MemoryBudget},
+    nii_image::NiiImage,
+    rel_nii_files_iter::RelNiiFilesIter,
+    resume_manifest::{CompletedInputs, Fingerprint},
     target_path::TargetImageDir,
+    volume_access::VolumeAccess,
+    ConvertOptions, MAX_DIMS,
 };
 use arrayvec::ArrayVec;
-use pyo3::prelude::*;
+use pyo3::{prelude::*, types::PyDict};
 
-// Iterator over pairs of (png_stub, nii_image) for all nii files in nii_files
-// where png_stub is a path to a directory where the png files for the NIFTI volume will be saved.
-pub(crate) struct RelNiiImagesIter<'a>(RelNiiFilesIter<'a>);
+// Iterator over triples of (png_stub, source, nii_image) for all nii files in nii_files
+// where png_stub is a path to a directory where the png files for the NIFTI volume will be saved
+// and source is the path to the file (or the DICOM series folder).
+pub(crate) struct RelNiiImagesIter<'a> {
+    files: RelNiiFilesIter<'a>,
+    access: VolumeAccess,
+    memory_budget: Option<MemoryBudget>,
+}
 
 impl<'a> RelNiiImagesIter<'a> {
+    /// Uses `volume_access` and `memory_budget` of the options, see also [`RelNiiFilesIter::new`]
     pub(crate) fn new(
         nib: &'a PyModule,
         os: &'a PyModule,
-        nii_files: &'a str,
-        base_png_stub: PathBuf,
+        options: &ConvertOptions,
+        completed: Option<CompletedInputs>,
     ) -> Result<Self, ErrorTy> {
-        Ok(Self(RelNiiFilesIter::new(
-            nib,
-            os,
-            nii_files,
-            base_png_stub,
-        )?))
+        Ok(Self {
+            files: RelNiiFilesIter::new(nib, os, options, completed)?,
+            access: options.volume_access,
+            memory_budget: options.memory_budget,
+        })
+    }
+
+    /// See [`RelNiiFilesIter::progress`]
+    pub(crate) fn progress(&self) -> (usize, usize) {
+        self.files.progress()
+    }
+
+    /// See [`RelNiiFilesIter::pending_series`]
+    pub(crate) fn pending_series(&self) -> usize {
+        self.files.pending_series()
+    }
+
+    /// See [`RelNiiFilesIter::take_skipped`]
+    pub(crate) fn take_skipped(&mut self) -> Vec<PathBuf> {
+        self.files.take_skipped()
+    }
+
+    /// See [`RelNiiFilesIter::take_fingerprint`]
+    pub(crate) fn take_fingerprint(&mut self, target_dir: &Path) -> Option<Fingerprint> {
+        self.files.take_fingerprint(target_dir)
+    }
+
+    /// Decides how to read the volume (if at all) based on the shape and the data type from the header.
+    /// [`VolumeAccess::Auto`] is never returned.
+    fn budgeted_access(
+        &self,
+        hdr: &PyAny,
+        dims: &[isize; MAX_DIMS],
+        source: &Path,
+    ) -> Result<VolumeAccess, ErrorTy> {
+        let access = self.access.resolved();
+        let Some(budget) = self.memory_budget else {
+            return Ok(access);
+        };
+        let itemsize = hdr
+            .call_method0("get_data_dtype")?
+            .getattr("itemsize")?
+            .extract::<u64>()?;
+        let estimate = estimate_memory(dims, itemsize, access);
+        const MIB: u64 = 1 << 20;
+        match budget.check(dims, itemsize, access) {
+            BudgetDecision::Accept(access) => {
+                tracing::info!(
+                    source = %source.display(),
+                    estimate_mib = estimate / MIB,
+                    budget_mib = budget.bytes / MIB,
+                    ?access,
+                    "Estimated memory is within the budget"
+                );
+                Ok(access)
+            }
+            BudgetDecision::Reject => {
+                tracing::warn!(
+                    source = %source.display(),
+                    estimate_mib = estimate / MIB,
+                    budget_mib = budget.bytes / MIB,
+                    "Estimated memory exceeds the budget"
+                );
+                Err(OverMemoryBudget(
+                    source.display().to_string(),
+                    estimate,
+                    budget.bytes,
+                ))
+            }
+        }
     }
 
-    fn nii_obj2nii_image(nii_obj: &'a PyAny) -> Result<NiiImage<'a>, ErrorTy> {
+    fn nii_obj2nii_image(
+        &self,
+        source: &Path,
+        nii_obj: &'a PyAny,
+    ) -> Result<NiiImage<'a>, ErrorTy> {
         // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L78
         let hdr = nii_obj.getattr("header")?;
 
         // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L81
         let nii_shape = hdr.call_method("get_data_shape", (), None)?;
 
+        let ndim = nii_shape.len()?;
+        if ndim != 3 && ndim != 4 {
+            return Err(UnsupportedDimensionality(
+                ndim,
+                source.display().to_string(),
+            ));
+        }
+
         let mut dims = ArrayVec::new_const();
 
         for i in 0..=2 {
             dims.push(nii_shape.get_item(i)?.extract::<isize>()?);
         }
 
-        dims.push(match nii_shape.len()? {
+        dims.push(match ndim {
             3 => 1,
-            4 => nii_shape.get_item(3)?.extract::<isize>()?,
-            // TODO: specify which image is invalid
-            _ => panic!("Unexpected dimensionality of the image (3 or 4 expected)"),
+            _ => nii_shape.get_item(3)?.extract::<isize>()?,
         });
 
-        let fdata = nii_obj.call_method("get_fdata", (), None)?;
+        let access = self.budgeted_access(
+            hdr,
+            dims.as_slice()
+                .try_into()
+                .expect("all dimensions are known"),
+            source,
+        )?;
+
+        let data = match access {
+            VolumeAccess::Lazy => nii_obj.getattr("dataobj")?,
+            VolumeAccess::Auto => unreachable!("resolved by `budgeted_access`"),
+            VolumeAccess::Full(dtype) => nii_obj.call_method(
+                "get_fdata",
+                (),
+                Some({
+                    let kwargs = PyDict::new(nii_obj.py());
+                    kwargs.set_item("dtype", dtype.as_numpy_dtype())?;
+                    kwargs
+                }),
+            )?,
+        };
+
+        let affine = nii_obj
+            .getattr("affine")?
+            .call_method0("tolist")?
+            .extract()?;
 
-        Ok(NiiImage { fdata, dims })
+        Ok(NiiImage {
+            hdr,
+            affine,
+            data,
+            dims,
+            access,
+        })
     }
 
     fn nii_obj_res2nii_image_res(
-        res: Result<(TargetImageDir<'a>, &'a PyAny), ErrorTy>,
-    ) -> Result<(TargetImageDir<'a>, NiiImage<'a>), ErrorTy> {
-        let (png_stub, nii_obj) = res?;
-        let nii_image = Self::nii_obj2nii_image(nii_obj)?;
-        Ok((png_stub, nii_image))
+        &self,
+        res: Result<(TargetImageDir<'a>, PathBuf, &'a PyAny), ErrorTy>,
+    ) -> Result<(TargetImageDir<'a>, PathBuf, NiiImage<'a>), ErrorTy> {
+        let (png_stub, source, nii_obj) = res?;
+        let nii_image = self
+            .nii_obj2nii_image(&source, nii_obj)
+            .map_err(|e| e.in_file(source.clone()))?;
+        Ok((png_stub, source, nii_image))
     }
 }
 
 impl<'a> Iterator for RelNiiImagesIter<'a> {
-    type Item = Result<(TargetImageDir<'a>, NiiImage<'a>), ErrorTy>;
+    type Item = Result<(TargetImageDir<'a>, PathBuf, NiiImage<'a>), ErrorTy>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().map(Self::nii_obj_res2nii_image_res)
+        let res = self.files.next()?;
+        Some(self.nii_obj_res2nii_image_res(res))
     }
 }
@@ -2,17 +2,39 @@ use crate::{
     error_ty::ErrorTy, nii_image::NiiImage,
     rescaled_intensity_nii_slice::RescaledIntensityNiiSlice, SECONDARY_DIMS,
 };
-use pyo3::prelude::*;
+use pyo3::{prelude::*, types::PyDict};
 
-pub(crate) struct RescaledIntensityNiiImage<'a>(NiiImage<'a>);
+pub(crate) struct RescaledIntensityNiiImage<'a> {
+    image: NiiImage<'a>,
+    // from skimage import exposure
+    exposure: &'a PyAny,
+    // the range of intensities mapped to [0, 1]
+    in_range: (f64, f64),
+}
 
 impl<'a> RescaledIntensityNiiImage<'a> {
-    pub(crate) fn new(nii_image: NiiImage<'a>) -> Self {
-        Self(nii_image)
+    pub(crate) fn new(image: NiiImage<'a>, exposure: &'a PyAny, in_range: (f64, f64)) -> Self {
+        Self {
+            image,
+            exposure,
+            in_range,
+        }
     }
 
     pub(crate) fn dim(&self, i: usize) -> isize {
-        self.0.dims[i]
+        self.image.dims[i]
+    }
+
+    pub(crate) fn in_range(&self) -> (f64, f64) {
+        self.in_range
+    }
+
+    /// World-space coordinates of the voxel
+    pub(crate) fn position(&self, voxel: [f64; 3]) -> [f64; 3] {
+        let affine = &self.image.affine;
+        [0, 1, 2].map(|row| {
+            (0..3).map(|col| affine[row][col] * voxel[col]).sum::<f64>() + affine[row][3]
+        })
     }
 
     pub(crate) fn get_slice(
@@ -20,8 +42,22 @@ impl<'a> RescaledIntensityNiiImage<'a> {
         py: Python<'a>,
         index: [isize; SECONDARY_DIMS],
     ) -> Result<RescaledIntensityNiiSlice<'a>, ErrorTy> {
-        self.0
-            .get_slice(py, index)
-            .map(|slice| (RescaledIntensityNiiSlice::new(slice, self.dim(0), self.dim(1))))
+        let slice = self.image.get_slice(py, index)?;
+        // Rescale to 0..1 float64
+        let slice = self.exposure.call_method(
+            "rescale_intensity",
+            (slice,),
+            Some({
+                let dict = PyDict::new(py);
+                dict.set_item("in_range", self.in_range)?;
+                dict.set_item("out_range", (0.0, 1.0))?;
+                dict
+            }),
+        )?;
+        Ok(RescaledIntensityNiiSlice::new(
+            slice,
+            self.dim(0),
+            self.dim(1),
+        ))
     }
 }
@@ -1,12 +1,13 @@
 use image::RgbImage;
 use pyo3::{PyAny, Python};
-use pyo3::types::PyUnicode;
 
-use crate::error_ty::ErrorTy;
+use crate::{error_ty::ErrorTy, paths::py_path, ubyte_slice::UbyteSlice};
 
 pub(crate) struct RescaledIntensityNiiSlice<'a> {
     slice: &'a PyAny,
+    // the first dimension of the slice
     width: isize,
+    // the second dimension of the slice
     height: isize,
 }
 
@@ -68,6 +69,32 @@ impl<'a> RescaledIntensityNiiSlice<'a> {
         save_ubyte_rgb_grayscale_slice(path, ubyte_sz_rgb, io, Image, ImageOps)
     }
 
+    /// Converts the slice to `uint8` the same way [`save`](Self::save) does
+    /// and copies it out of the Python heap.
+    pub(crate) fn to_ubyte(&self, img_as_ubyte: &PyAny) -> Result<UbyteSlice, ErrorTy> {
+        let ubyte = img_as_ubyte.call1((self.slice,))?;
+        // tobytes() returns the C-ordered data even for non-contiguous views
+        let bytes = ubyte.call_method0("tobytes")?;
+        Ok(UbyteSlice {
+            voxels: bytes.extract::<&[u8]>()?.to_vec(),
+            dims: [self.width as usize, self.height as usize],
+        })
+    }
+
+    /// Copies the rescaled intensities out of the Python heap, C-ordered
+    pub(crate) fn to_f64(&self) -> Result<Vec<f64>, ErrorTy> {
+        let bytes = self
+            .slice
+            .call_method1("astype", ("float64",))?
+            .call_method0("tobytes")?;
+        Ok(bytes
+            .extract::<&[u8]>()?
+            .chunks_exact(8)
+            .map(|v| f64::from_ne_bytes(v.try_into().expect("chunks of 8 bytes")))
+            .collect())
+    }
+
+    #[allow(dead_code)]
     pub(crate) fn as_rgb_image(
         &self,
         py: Python,
@@ -83,13 +110,14 @@ impl<'a> RescaledIntensityNiiSlice<'a> {
             temp_dir.push("tmp.png");
             temp_dir
         };
-        let temp_file_py = PyUnicode::new(py, temp_file.to_str().ok_or(ErrorTy::TempDirNotUtf8)?);
-        self.save(&temp_file_py, io, color, img_as_ubyte, Image, ImageOps)?;
+        let temp_file_py = py_path(py, &temp_file)?;
+        self.save(temp_file_py, io, color, img_as_ubyte, Image, ImageOps)?;
         let img = image::open(&temp_file)
             .map_err(|e| ErrorTy::ImageOpenFailed(e, temp_file.to_string_lossy().to_string()))?;
         Ok(img.to_rgb8())
     }
 
+    #[allow(dead_code)]
     pub(crate) fn as_raw_rgb_image_buffer(
         &self,
         py: Python,
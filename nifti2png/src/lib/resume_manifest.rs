@@ -0,0 +1,224 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    paths::{os_from_bytes, os_to_bytes},
+    target_path::{TargetFile, TargetImageDir},
+    volume_format::VolumeFormat,
+    ConvertOptions,
+};
+
+/// Name of the manifest in the root of the output
+pub(crate) const RESUME_MANIFEST_NAME: &str = ".nifti2png-completed.tsv";
+
+/// State of a source when it was converted
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    // total size of the member files
+    size: u64,
+    // SHA-256 of the path, size and mtime of every member file, see `members`
+    state: String,
+    // `settings` of the manifest
+    settings: String,
+}
+
+/// Files whose contents make up the source: the file itself and the second file of a pair
+/// or all files of a DICOM series folder, in a stable order
+fn members(source: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if source.is_dir() {
+        let mut files = Vec::new();
+        let mut dirs = vec![source.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                // Like `os.walk`, which finds the DICOM files, symlinked directories aren't followed
+                match entry.file_type()?.is_dir() {
+                    true => dirs.push(entry.path()),
+                    false => files.push(entry.path()),
+                }
+            }
+        }
+        files.sort();
+        return Ok(files);
+    }
+    let mut files = vec![source.to_path_buf()];
+    let (Some(name), Some(parent)) = (source.file_name(), source.parent()) else {
+        return Ok(files);
+    };
+    let (Some(format), Some(stem)) = (VolumeFormat::detect(name), VolumeFormat::stem(name)) else {
+        return Ok(files);
+    };
+    if format.companion_extensions().is_empty() {
+        return Ok(files);
+    }
+    let stem = os_to_bytes(&stem);
+    for entry in std::fs::read_dir(parent)? {
+        let path = entry?.path();
+        let name = os_to_bytes(path.file_name().unwrap_or_default());
+        let is_companion = name.starts_with(&stem)
+            && format
+                .companion_extensions()
+                .iter()
+                .any(|ext| name[stem.len()..].eq_ignore_ascii_case(ext.as_bytes()));
+        if is_companion {
+            files.push(path);
+        }
+    }
+    files[1..].sort();
+    Ok(files)
+}
+
+impl Fingerprint {
+    fn new(source: &Path, settings: &str) -> std::io::Result<Self> {
+        let mut size = 0;
+        let mut state = Sha256::new();
+        for member in members(source)? {
+            let metadata = std::fs::metadata(&member)?;
+            let mtime = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |mtime| mtime.as_nanos());
+            size += metadata.len();
+            state.update(os_to_bytes(member.as_os_str()));
+            state.update(format!("\t{}\t{mtime}\n", metadata.len()));
+        }
+        Ok(Self {
+            size,
+            state: hex::encode(state.finalize()),
+            settings: settings.to_string(),
+        })
+    }
+}
+
+/// Sources completed by the previous runs, as recorded in the manifest when the conversion started
+pub(crate) struct CompletedInputs {
+    settings: String,
+    // by target directory, which is unique even for the series of a DICOM folder
+    fingerprints: HashMap<PathBuf, Fingerprint>,
+}
+
+impl CompletedInputs {
+    /// Current state of `source`, taken before it is converted
+    /// so that the changes made during the conversion aren't missed
+    pub(crate) fn fingerprint(&self, source: &Path) -> std::io::Result<Fingerprint> {
+        Fingerprint::new(source, &self.settings)
+    }
+
+    /// Whether the source with the `current` fingerprint was converted to `target_dir`
+    /// with the same settings and neither of them has changed since then
+    pub(crate) fn contains(&self, target_dir: &TargetImageDir, current: &Fingerprint) -> bool {
+        self.fingerprints.get(&target_dir.path) == Some(current) && target_dir.is_complete()
+    }
+}
+
+/// Append-only record of the converted sources, one line per completed volume:
+///
+/// `size \t state \t settings \t target_dir \t source`
+///
+/// The paths are stored as raw bytes, so the ones that aren't UTF-8 are matched exactly.
+///
+/// A volume is only recorded after all its slices are written, so the volumes
+/// interrupted half-way are converted again by the next run.
+pub(crate) struct ResumeManifest {
+    path: PathBuf,
+    file: File,
+}
+
+impl ResumeManifest {
+    /// Output settings that change the exported slices
+    fn settings(options: &ConvertOptions) -> String {
+        let settings = format!(
+            "minmax={:?};access={:?}",
+            options.minmax, options.volume_access
+        );
+        // The manifests of the PNG exports stay valid
+        match options.array_export {
+            Some(arrays) => format!("{settings};arrays={arrays:?}"),
+            None => settings,
+        }
+    }
+
+    /// Opens (or creates) the manifest in `root` and reads the completed sources
+    pub(crate) fn open(
+        root: &TargetImageDir,
+        options: &ConvertOptions,
+    ) -> Result<(Self, CompletedInputs), ErrorTy> {
+        let path = root.path.join(RESUME_MANIFEST_NAME);
+        let settings = Self::settings(options);
+
+        let mut fingerprints = HashMap::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).split(b'\n') {
+                    let line =
+                        line.map_err(|e| ManifestReadFailed(e, path.display().to_string()))?;
+                    // Lines that can't be parsed, e.g. the last one of an interrupted write, are ignored
+                    let [size, state, recorded_settings, target_dir, _source] =
+                        line.splitn(5, |&byte| byte == b'\t').collect::<Vec<_>>()[..]
+                    else {
+                        continue;
+                    };
+                    let (Ok(size), Ok(state), Ok(recorded_settings)) = (
+                        String::from_utf8_lossy(size).parse(),
+                        std::str::from_utf8(state),
+                        std::str::from_utf8(recorded_settings),
+                    ) else {
+                        continue;
+                    };
+                    // The later lines override the earlier ones
+                    fingerprints.insert(
+                        PathBuf::from(os_from_bytes(target_dir.to_vec())),
+                        Fingerprint {
+                            size,
+                            state: state.to_string(),
+                            settings: recorded_settings.to_string(),
+                        },
+                    );
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(ManifestReadFailed(e, path.display().to_string())),
+        };
+
+        let file = TargetFile(path.clone()).append()?;
+        Ok((
+            Self { path, file },
+            CompletedInputs {
+                settings,
+                fingerprints,
+            },
+        ))
+    }
+
+    /// Records that `source` with the `fingerprint` taken before the conversion
+    /// is fully converted to `target_dir`
+    pub(crate) fn record(
+        &mut self,
+        target_dir: &Path,
+        source: &Path,
+        fingerprint: &Fingerprint,
+    ) -> Result<(), ErrorTy> {
+        let write_failed = |e| ManifestWriteFailed(e, self.path.display().to_string());
+        let Fingerprint {
+            size,
+            state,
+            settings,
+        } = fingerprint;
+        // A single write, so that the lines of the worker processes sharing the manifest don't mix
+        let mut line = format!("{size}\t{state}\t{settings}\t").into_bytes();
+        line.extend_from_slice(&os_to_bytes(target_dir.as_os_str()));
+        line.push(b'\t');
+        line.extend_from_slice(&os_to_bytes(source.as_os_str()));
+        line.push(b'\n');
+        self.file.write_all(&line).map_err(write_failed)?;
+        self.file.flush().map_err(write_failed)
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    ubyte_slice::UbyteSlice,
+    OnError, OutputSink,
+};
+
+/// Slice that waits to be encoded and written to `path`
+pub(crate) struct EncodeJob {
+    pub(crate) slice: UbyteSlice,
+    pub(crate) path: PathBuf,
+    // the directory of the volume the slice belongs to, unique even for the series of a DICOM folder
+    pub(crate) target_dir: PathBuf,
+    // the file the slice belongs to
+    pub(crate) source: PathBuf,
+}
+
+/// Slices that failed to save with [`OnError::Continue`], along with their target directories and sources
+type Failures = Vec<(PathBuf, PathBuf, ErrorTy)>;
+
+/// Number of submitted slices of every target directory that are not saved yet
+/// and whether any slice of the directory failed to save
+type Outstanding = Arc<Mutex<HashMap<PathBuf, (usize, bool)>>>;
+
+/// Threads that encode [`UbyteSlice`]s as PNGs and write them to the [`OutputSink`].
+///
+/// None of the threads touches Python, so they keep working while the
+/// calling thread extracts the next slices with the GIL held.
+pub(crate) struct SliceEncoderPool {
+    sender: SyncSender<EncodeJob>,
+    workers: Vec<JoinHandle<Result<Failures, ErrorTy>>>,
+    outstanding: Outstanding,
+    // set by the first thread that fails with `OnError::Stop`
+    stopped: Arc<AtomicBool>,
+}
+
+fn work(
+    receiver: Arc<Mutex<Receiver<EncodeJob>>>,
+    outstanding: Outstanding,
+    stopped: Arc<AtomicBool>,
+    on_error: OnError,
+    sink: Arc<dyn OutputSink>,
+) -> Result<Failures, ErrorTy> {
+    let mut failures = Vec::new();
+    loop {
+        // The lock is released as soon as the job is received
+        let job = receiver.lock().map(|receiver| receiver.recv());
+        match job {
+            Ok(Ok(EncodeJob {
+                slice,
+                path,
+                target_dir,
+                source,
+            })) => {
+                let res = slice.save(&*sink, &path);
+                if let Ok(mut outstanding) = outstanding.lock() {
+                    if let Some((count, failed)) = outstanding.get_mut(&target_dir) {
+                        *count -= 1;
+                        *failed |= res.is_err();
+                    }
+                }
+                match (res, on_error) {
+                    (Ok(()), _) => (),
+                    (Err(e), OnError::Stop) => {
+                        stopped.store(true, Ordering::Relaxed);
+                        return Err(e.in_file(source));
+                    }
+                    (Err(e), OnError::Continue) => failures.push((target_dir, source, e)),
+                }
+            }
+            // The sender is dropped, i.e. there are no more jobs
+            Ok(Err(_)) => return Ok(failures),
+            // Another worker panicked while holding the lock
+            Err(_) => return Err(EncoderThreadPanicked),
+        }
+    }
+}
+
+impl SliceEncoderPool {
+    pub(crate) fn new(threads: NonZeroUsize, on_error: OnError, sink: Arc<dyn OutputSink>) -> Self {
+        // Bounds the number of extracted slices waiting in memory
+        let (sender, receiver) = sync_channel(threads.get() * 2);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let outstanding = Outstanding::default();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let workers = (0..threads.get())
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let outstanding = Arc::clone(&outstanding);
+                let stopped = Arc::clone(&stopped);
+                let sink = Arc::clone(&sink);
+                std::thread::spawn(move || work(receiver, outstanding, stopped, on_error, sink))
+            })
+            .collect();
+        Self {
+            sender,
+            workers,
+            outstanding,
+            stopped,
+        }
+    }
+
+    /// Blocks until one of the threads is ready to take the job.
+    ///
+    /// Fails if any thread has stopped with [`OnError::Stop`] or all threads have stopped,
+    /// in which case [`finish`](Self::finish) returns the reason.
+    pub(crate) fn submit(&self, job: EncodeJob) -> Result<(), ErrorTy> {
+        if self.is_stopped() {
+            return Err(EncoderThreadsStopped);
+        }
+        if let Ok(mut outstanding) = self.outstanding.lock() {
+            outstanding.entry(job.target_dir.clone()).or_default().0 += 1;
+        }
+        self.sender.send(job).map_err(|_| EncoderThreadsStopped)
+    }
+
+    /// Whether a thread has stopped with [`OnError::Stop`]
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Whether all submitted slices of `target_dir` are saved successfully.
+    /// `None` while some of them are still in progress.
+    ///
+    /// Once the outcome is known, it is forgotten.
+    pub(crate) fn take_written(&self, target_dir: &Path) -> Option<bool> {
+        let mut outstanding = self.outstanding.lock().ok()?;
+        match outstanding.get(target_dir) {
+            Some((0, failed)) => {
+                let written = !failed;
+                outstanding.remove(target_dir);
+                Some(written)
+            }
+            Some(_) => None,
+            None => Some(true),
+        }
+    }
+
+    /// Waits until all submitted jobs are done and returns the first error, if any.
+    ///
+    /// With [`OnError::Continue`], the slices that failed to save are returned instead.
+    pub(crate) fn finish(self) -> Result<Failures, ErrorTy> {
+        let Self {
+            sender, workers, ..
+        } = self;
+        drop(sender);
+        let mut res = Ok(Vec::new());
+        for worker in workers {
+            let worker_res = worker.join().unwrap_or(Err(EncoderThreadPanicked));
+            match (&mut res, worker_res) {
+                (Ok(failures), Ok(worker_failures)) => failures.extend(worker_failures),
+                (Ok(_), Err(e)) => res = Err(e),
+                (Err(_), _) => (),
+            }
+        }
+        res
+    }
+}
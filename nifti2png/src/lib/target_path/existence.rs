@@ -1,3 +1,4 @@
+/// Whether the target path was found on disk when it was last checked
 #[repr(u8)]
 pub enum Existence {
     True,
@@ -1,5 +1,5 @@
 #[repr(u8)]
 pub(crate) enum Kind {
     ImageDir,
-    // File,
+    File,
 }
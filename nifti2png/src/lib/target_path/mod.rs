@@ -1,12 +1,19 @@
 use core::marker::PhantomData;
-use std::{path::PathBuf, fs::create_dir_all};
+use std::{
+    fs::{create_dir_all, remove_dir_all, remove_file, rename, File, OpenOptions},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 mod kind;
 pub mod existence;
 use kind::Kind;
 use existence::Existence;
 
-use crate::error_ty::ErrorTy;
+use crate::{error_ty::ErrorTy, OutputSink, OverwritePolicy};
+
+/// Empty file written to the directory of a volume once all its slices are written
+pub(crate) const COMPLETE_MARKER_NAME: &str = ".nifti2png-complete";
 
 pub(crate) struct TargetPath<'a, const KIND_ID: u8, const EXISTENCE_ID: u8> {
     pub path: PathBuf,
@@ -14,22 +21,280 @@ pub(crate) struct TargetPath<'a, const KIND_ID: u8, const EXISTENCE_ID: u8> {
 }
 
 pub(crate) type TargetImageDir<'a> = TargetPath<'a, { Kind::ImageDir as u8 }, { Existence::Unknown as u8 }>;
-// pub(crate) type TargetFile<'a> = TargetPath<'a, { Kind::File as u8 }>;
+pub(crate) type ExistingImageDir<'a> =
+    TargetPath<'a, { Kind::ImageDir as u8 }, { Existence::True as u8 }>;
+pub(crate) type NewImageDir<'a> =
+    TargetPath<'a, { Kind::ImageDir as u8 }, { Existence::False as u8 }>;
+pub(crate) type TargetFile<'a> = TargetPath<'a, { Kind::File as u8 }, { Existence::Unknown as u8 }>;
+
 #[allow(non_snake_case)]
 pub(crate) fn TargetImageDir<'a>(dir: PathBuf) -> TargetImageDir<'a> {
-    TargetPath {
-        path: dir,
-        phantom: PhantomData,
+    TargetPath::new(dir)
+}
+
+#[allow(non_snake_case)]
+pub(crate) fn TargetFile<'a>(file: PathBuf) -> TargetFile<'a> {
+    TargetPath::new(file)
+}
+
+/// Outcome of [`TargetImageDir::check`]
+pub(crate) enum CheckedImageDir<'a> {
+    Existing(ExistingImageDir<'a>),
+    New(NewImageDir<'a>),
+}
+
+impl<'a, const KIND_ID: u8, const EXISTENCE_ID: u8> TargetPath<'a, KIND_ID, EXISTENCE_ID> {
+    fn new(path: PathBuf) -> Self {
+        TargetPath {
+            path,
+            phantom: PhantomData,
+        }
+    }
+
+    fn display(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    fn try_exists(&self) -> Result<bool, ErrorTy> {
+        self.path
+            .try_exists()
+            .map_err(|e| ErrorTy::TryExistsFailed(e, self.display()))
     }
 }
 
 impl<'a> TargetImageDir<'a> {
-    pub(crate) fn ensure_exists(&self) -> Result<(), ErrorTy> {
-        let Self { path, .. } = &*self;
-        if !path.try_exists().map_err(|e| ErrorTy::TryExistsFailed(e, path.to_string_lossy().into_owned()))?
-        {
-            create_dir_all(path).map_err(|e| ErrorTy::CreateDirAllFailed(e, path.to_string_lossy().into_owned()))?;
+    pub(crate) fn check(&self) -> Result<CheckedImageDir<'a>, ErrorTy> {
+        if !self.try_exists()? {
+            return Ok(CheckedImageDir::New(TargetPath::new(self.path.clone())));
+        }
+        if !self.path.is_dir() {
+            return Err(ErrorTy::TargetNotADirectory(self.display()));
+        }
+        Ok(CheckedImageDir::Existing(TargetPath::new(
+            self.path.clone(),
+        )))
+    }
+
+    /// Creates the directory unless it exists. The files in it are kept.
+    pub(crate) fn ensure_exists(&self) -> Result<ExistingImageDir<'a>, ErrorTy> {
+        match self.check()? {
+            CheckedImageDir::Existing(dir) => Ok(dir),
+            CheckedImageDir::New(dir) => dir.create(),
+        }
+    }
+
+    /// Whether all slices were written to the directory by a previous export
+    pub(crate) fn is_complete(&self) -> bool {
+        self.path.join(COMPLETE_MARKER_NAME).is_file()
+    }
+
+    /// Directories of the sinks that aren't local exist implicitly, as prefixes of the paths
+    pub(crate) fn in_remote_sink(&self) -> ExistingImageDir<'a> {
+        TargetPath::new(self.path.clone())
+    }
+
+    /// Returns the empty directory to write to according to the policy
+    /// or `None` if the directory should be skipped.
+    ///
+    /// The temporary files left behind by an interrupted export are removed first.
+    pub(crate) fn prepare(
+        &self,
+        policy: OverwritePolicy,
+    ) -> Result<Option<ExistingImageDir<'a>>, ErrorTy> {
+        let existing = match self.check()? {
+            CheckedImageDir::New(dir) => return dir.create().map(Some),
+            CheckedImageDir::Existing(dir) => {
+                dir.remove_temp_files()?;
+                if dir.is_empty()? {
+                    return Ok(Some(dir));
+                }
+                dir
+            }
         };
+        match policy {
+            OverwritePolicy::Error => Err(ErrorTy::TargetExists(existing.display())),
+            OverwritePolicy::SkipExisting if self.is_complete() => Ok(None),
+            // The export was interrupted half-way
+            OverwritePolicy::SkipExisting => existing.remove()?.create().map(Some),
+            OverwritePolicy::Overwrite => existing.remove()?.create().map(Some),
+            OverwritePolicy::Versioned => existing.next_version()?.create().map(Some),
+        }
+    }
+}
+
+impl<'a> ExistingImageDir<'a> {
+    fn is_empty(&self) -> Result<bool, ErrorTy> {
+        let mut entries =
+            std::fs::read_dir(&self.path).map_err(|e| ErrorTy::ReadDirFailed(e, self.display()))?;
+        Ok(entries.next().is_none())
+    }
+
+    /// Removes the [`temp_sibling`](TargetFile::temp_sibling)s of the files in the directory
+    fn remove_temp_files(&self) -> Result<(), ErrorTy> {
+        let entries =
+            std::fs::read_dir(&self.path).map_err(|e| ErrorTy::ReadDirFailed(e, self.display()))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| ErrorTy::ReadDirFailed(e, self.display()))?
+                .path();
+            if is_temp_sibling(&path) && path.is_file() {
+                remove_file(&path)
+                    .map_err(|e| ErrorTy::RemoveFileFailed(e, path.display().to_string()))?;
+            }
+        }
         Ok(())
     }
+
+    fn remove(self) -> Result<NewImageDir<'a>, ErrorTy> {
+        remove_dir_all(&self.path).map_err(|e| ErrorTy::RemoveDirAllFailed(e, self.display()))?;
+        Ok(TargetPath::new(self.path))
+    }
+
+    /// The first sibling `<dir>-v<n>` that doesn't exist, starting with `n = 2`
+    fn next_version(&self) -> Result<NewImageDir<'a>, ErrorTy> {
+        let name = self
+            .path
+            .file_name()
+            .expect("the directory of a volume has a name")
+            .to_string_lossy()
+            .into_owned();
+        for n in 2.. {
+            let candidate = TargetImageDir(self.path.with_file_name(format!("{name}-v{n}")));
+            if let CheckedImageDir::New(dir) = candidate.check()? {
+                return Ok(dir);
+            }
+        }
+        unreachable!("there are only so many directories")
+    }
+}
+
+impl<'a> ExistingImageDir<'a> {
+    /// Marks the directory complete, see [`TargetImageDir::is_complete`]
+    pub(crate) fn mark_complete(&self, sink: &dyn OutputSink) -> Result<(), ErrorTy> {
+        sink.write(&self.path.join(COMPLETE_MARKER_NAME), &[])
+    }
+}
+
+impl<'a> NewImageDir<'a> {
+    pub(crate) fn create(self) -> Result<ExistingImageDir<'a>, ErrorTy> {
+        create_dir_all(&self.path).map_err(|e| ErrorTy::CreateDirAllFailed(e, self.display()))?;
+        Ok(TargetPath::new(self.path))
+    }
+}
+
+/// Whether the name is one of a [`TargetFile::temp_sibling`]
+fn is_temp_sibling(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let Some(name) = name.strip_prefix('.') else {
+        return false;
+    };
+    let tag = match name.split('.').collect::<Vec<_>>()[..] {
+        [_, .., tag, "tmp"] | [_, .., tag, "tmp", _] => tag,
+        _ => return false,
+    };
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    tag.split_once('-')
+        .is_some_and(|(pid, n)| is_number(pid) && is_number(n))
+}
+
+impl<'a> TargetFile<'a> {
+    /// Creates (or truncates) the file along with the missing parent directories
+    pub(crate) fn create(&self) -> Result<File, ErrorTy> {
+        self.ensure_parent_exists()?;
+        File::create(&self.path).map_err(|e| ErrorTy::CreateFileFailed(e, self.display()))
+    }
+
+    /// Opens the file for appending, creating it along with the missing parent directories
+    pub(crate) fn append(&self) -> Result<File, ErrorTy> {
+        self.ensure_parent_exists()?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| ErrorTy::CreateFileFailed(e, self.display()))
+    }
+
+    /// Hidden sibling with the same extension, e.g. `.0000.1234-5.tmp.png` for `0000.png`,
+    /// so that the encoders still infer the format from the name.
+    ///
+    /// The name is unique to the call (process id and a counter),
+    /// so concurrent writers of the same file don't share it.
+    pub(crate) fn temp_sibling(&self) -> PathBuf {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let tag = format!(
+            "{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(extension) => format!(".{stem}.{tag}.tmp.{}", extension.to_string_lossy()),
+            None => format!(".{stem}.{tag}.tmp"),
+        };
+        self.path.with_file_name(name)
+    }
+
+    /// Moves the finished `temp` file into place. The rename is atomic within a filesystem,
+    /// so the file is either missing, or complete.
+    pub(crate) fn replace_with(&self, temp: &Path) -> Result<(), ErrorTy> {
+        rename(temp, &self.path).map_err(|e| ErrorTy::RenameFailed(e, self.display()))
+    }
+
+    /// Writes the file to its [`temp_sibling`](Self::temp_sibling) and renames it into place.
+    /// The missing parent directories are created. The sibling is removed if `write` fails.
+    pub(crate) fn write_atomically(
+        &self,
+        write: impl FnOnce(&Path) -> Result<(), ErrorTy>,
+    ) -> Result<(), ErrorTy> {
+        self.ensure_parent_exists()?;
+        let temp = self.temp_sibling();
+        let res = write(&temp).and_then(|()| self.replace_with(&temp));
+        if res.is_err() {
+            // The error of the write is more useful
+            let _ = remove_file(&temp);
+        }
+        res
+    }
+
+    fn ensure_parent_exists(&self) -> Result<(), ErrorTy> {
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            TargetImageDir(parent.to_path_buf()).ensure_exists()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_siblings_are_unique_and_recognized() {
+        let file = TargetFile(PathBuf::from("out/brain/0000.png"));
+        let (a, b) = (file.temp_sibling(), file.temp_sibling());
+        assert_ne!(a, b);
+        assert_eq!(a.parent(), Some(Path::new("out/brain")));
+        assert_eq!(a.extension(), Some("png".as_ref()));
+        assert!(is_temp_sibling(&a) && is_temp_sibling(&b));
+        assert!(is_temp_sibling(
+            &TargetFile(PathBuf::from("README")).temp_sibling()
+        ));
+    }
+
+    #[test]
+    fn other_hidden_files_are_not_temp_siblings() {
+        for name in [
+            ".tmp",
+            ".0000.tmp.png",
+            ".notes.tmp",
+            ".a.b-c.tmp.png",
+            "0000.1-2.tmp.png",
+        ] {
+            assert!(!is_temp_sibling(Path::new(name)), "{name}");
+        }
+    }
 }
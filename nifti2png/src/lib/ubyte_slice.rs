@@ -0,0 +1,66 @@
+use std::{io::Cursor, path::Path};
+
+use image::{DynamicImage, GrayImage, ImageBuffer, ImageOutputFormat, Luma, Pixel, Rgb, RgbImage};
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    orientation::pixel2voxel,
+    OutputSink,
+};
+
+/// Slice converted to `uint8` and copied out of the Python heap.
+///
+/// Unlike [`RescaledIntensityNiiSlice`](crate::rescaled_intensity_nii_slice::RescaledIntensityNiiSlice),
+/// it doesn't need the GIL and can be sent to other threads.
+pub(crate) struct UbyteSlice {
+    // C-ordered voxels of the slice
+    pub(crate) voxels: Vec<u8>,
+    // [height, width], i.e. the shape of the slice
+    pub(crate) dims: [usize; 2],
+}
+
+impl UbyteSlice {
+    /// Image with a pixel per voxel, oriented the same way as the PNGs saved with skimage and PIL.
+    /// The voxels cropped away from non-square slices are black.
+    fn oriented<P: Pixel<Subpixel = u8>>(
+        &self,
+        pixel: impl Fn(u8) -> P,
+    ) -> ImageBuffer<P, Vec<u8>> {
+        let [height, width] = self.dims;
+        ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+            match pixel2voxel(height, width, [x as usize, y as usize]) {
+                Some([row, col]) => pixel(self.voxels[row * width + col]),
+                None => pixel(0),
+            }
+        })
+    }
+
+    /// Grayscale RGB image oriented the same way as the PNGs saved with skimage and PIL
+    pub(crate) fn to_rgb_image(&self) -> RgbImage {
+        self.oriented(|v| Rgb([v, v, v]))
+    }
+
+    /// Single-channel image oriented the same way as [`to_rgb_image`](Self::to_rgb_image),
+    /// for masks whose values are labels rather than intensities
+    pub(crate) fn to_luma_image(&self) -> GrayImage {
+        self.oriented(|v| Luma([v]))
+    }
+
+    /// Encodes the image as a PNG and writes it to `path` of the sink
+    pub(crate) fn save(&self, sink: &dyn OutputSink, path: &Path) -> Result<(), ErrorTy> {
+        write_png(sink, path, DynamicImage::ImageRgb8(self.to_rgb_image()))
+    }
+
+    /// Same as [`save`](Self::save) but as a single-channel PNG, see [`to_luma_image`](Self::to_luma_image)
+    pub(crate) fn save_luma(&self, sink: &dyn OutputSink, path: &Path) -> Result<(), ErrorTy> {
+        write_png(sink, path, DynamicImage::ImageLuma8(self.to_luma_image()))
+    }
+}
+
+fn write_png(sink: &dyn OutputSink, path: &Path, image: DynamicImage) -> Result<(), ErrorTy> {
+    let mut png = Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .map_err(|e| ImageSaveFailed(e, path.to_string_lossy().into_owned()))?;
+    sink.write(path, png.get_ref())
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+/// How [`convert`](crate::convert) reads the voxels of the volumes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VolumeAccess {
+    /// [`Full`](Self::Full) with float64, so that the intensity range is found
+    /// without decoding the volume again. A volume over the memory budget is handled
+    /// as configured by [`OverBudget`](crate::OverBudget).
+    #[default]
+    Auto,
+    /// Only the exported slices are decoded, through nibabel's array proxy (`dataobj`).
+    /// Uncompressed files are memory-mapped.
+    ///
+    /// Without the `minmax`, the intensity range of the volume is found
+    /// with an extra pass over the slices, which decodes compressed files twice.
+    Lazy,
+    /// The whole volume is decoded at once with `get_fdata`, just like in the original Python code.
+    Full(FloatDtype),
+}
+
+impl VolumeAccess {
+    /// The access that [`Auto`](Self::Auto) stands for
+    pub(crate) fn resolved(self) -> Self {
+        match self {
+            VolumeAccess::Auto => VolumeAccess::Full(FloatDtype::Float64),
+            access => access,
+        }
+    }
+}
+
+/// Floating-point type of the decoded volumes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FloatDtype {
+    /// Half the memory of [`FloatDtype::Float64`], which is enough for most images
+    Float32,
+    /// The default of `get_fdata`
+    #[default]
+    Float64,
+}
+
+impl FloatDtype {
+    pub(crate) fn itemsize(self) -> u64 {
+        match self {
+            FloatDtype::Float32 => 4,
+            FloatDtype::Float64 => 8,
+        }
+    }
+
+    pub(crate) fn as_numpy_dtype(self) -> &'static str {
+        match self {
+            FloatDtype::Float32 => "float32",
+            FloatDtype::Float64 => "float64",
+        }
+    }
+}
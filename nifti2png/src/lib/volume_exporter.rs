@@ -0,0 +1,290 @@
+use std::{path::Path, sync::Arc};
+
+use pyo3::prelude::*;
+
+use crate::{
+    error_ty::ErrorTy,
+    nii_image::NiiImage,
+    npy::{self, NpyData, NpzWriter},
+    orientation::{orient, pixel2grid},
+    output_manifest::ManifestRow,
+    paths::py_path,
+    rescaled_intensity_nii_image::RescaledIntensityNiiImage,
+    slice_encoder_pool::{EncodeJob, SliceEncoderPool},
+    target_path::{ExistingImageDir, TargetFile},
+    ArrayContainer, ArrayDtype, ArrayExport, ArrayLayout, ConvertObserver, DirectorySink,
+    OutputSink, MAX_DIMS, SECONDARY_DIMS,
+};
+
+fn manifest_row(
+    nii_image: &RescaledIntensityNiiImage,
+    source: &Path,
+    [z, t]: [isize; SECONDARY_DIMS],
+    path: &Path,
+) -> ManifestRow {
+    let (height, width) = (nii_image.dim(0) as usize, nii_image.dim(1) as usize);
+    // The top left pixel may be black for non-square slices, see `orientation`
+    let [row, col] = pixel2grid(height, width, [0, 0]);
+    let [position_x, position_y, position_z] =
+        nii_image.position([row as f64, col as f64, z as f64]);
+    let (window_min, window_max) = nii_image.in_range();
+    ManifestRow {
+        source: source.display().to_string(),
+        t: t as usize,
+        z: z as usize,
+        position_x,
+        position_y,
+        position_z,
+        path: path.display().to_string(),
+        // the PNG has the shape of the slice, see `UbyteSlice::to_rgb_image`
+        width,
+        height,
+        window_min,
+        window_max,
+    }
+}
+
+/// Python modules and settings shared by all volumes converted by [`convert`](crate::convert)
+#[allow(non_snake_case)]
+pub(crate) struct VolumeExporter<'py> {
+    // from skimage import io, color, exposure, img_as_ubyte
+    pub(crate) io: &'py PyAny,
+    pub(crate) color: &'py PyAny,
+    pub(crate) exposure: &'py PyAny,
+    pub(crate) img_as_ubyte: &'py PyAny,
+    // https://github.com/PyO3/pyo3/discussions/3001
+    pub(crate) Image: &'py PyModule,
+    pub(crate) ImageOps: &'py PyModule,
+    pub(crate) minmax: Option<(u64, u64)>,
+    pub(crate) pool: Option<SliceEncoderPool>,
+    // `None` for the directories written by skimage and PIL
+    pub(crate) sink: Option<Arc<dyn OutputSink>>,
+    // Written instead of the PNGs
+    pub(crate) arrays: Option<ArrayExport>,
+}
+
+impl<'py> VolumeExporter<'py> {
+    /// Saves all slices of the volume to `png_stub` and describes them in `rows`, if any.
+    ///
+    /// Fails with [`ErrorTy::EncoderThreadsStopped`] if the slices can't be handed over
+    /// to the encoder threads anymore, in which case the reason is returned
+    /// by [`SliceEncoderPool::finish`].
+    pub(crate) fn export<'a>(
+        &self,
+        py: Python<'a>,
+        png_stub: &ExistingImageDir,
+        source: &Path,
+        nii_image: NiiImage<'a>,
+        observer: &mut dyn ConvertObserver,
+        mut rows: Option<&mut Vec<ManifestRow>>,
+    ) -> Result<(), ErrorTy>
+    where
+        'py: 'a,
+    {
+        tracing::info!(
+            dims = ?nii_image.dims,
+            target_dir = %png_stub.path.display(),
+            "Loaded the volume"
+        );
+
+        observer.file_started(
+            source,
+            &png_stub.path,
+            <[isize; MAX_DIMS]>::try_from(nii_image.dims.as_slice())
+                .expect("all dimensions are known")
+                .map(|d| d as usize),
+        );
+
+        let nii_image: RescaledIntensityNiiImage =
+            nii_image.rescale_intensity_to_unit_interval(py, self.exposure, self.minmax)?;
+        if self.arrays.is_some() {
+            return self.export_arrays(py, png_stub, source, &nii_image, observer, rows);
+        }
+
+        let t_count = nii_image.dim(MAX_DIMS - 1);
+        for t in 0..t_count {
+            observer.volume_started(t as usize, &png_stub.path);
+
+            for z in 0..nii_image.dim(MAX_DIMS - 2) {
+                // SAFETY: the objects of the slice don't leave the iteration
+                let slice_gil_pool = unsafe { py.new_pool() };
+                let py = slice_gil_pool.python();
+                let _slice_span = tracing::debug_span!("slice", t, z).entered();
+
+                let path = png_stub.path.join(format!("{z:04}.png"));
+
+                // Every volume writes the same paths, so only the last one is kept
+                // (see `convert`). The threads would save them in any order.
+                if self.pool.is_some() && t + 1 < t_count {
+                    tracing::debug!(path = %path.display(), "Skipped the overwritten slice");
+                    observer.slice_written(&path);
+                    if let Some(rows) = rows.as_deref_mut() {
+                        rows.push(manifest_row(&nii_image, source, [z, t], &path));
+                    }
+                    continue;
+                }
+
+                // https://github.com/korepanov/repalungs/blob/b8c3f62f3015ed89fc360a2a7166a29b56d293f4/back/converter/converter.py#L119-L122
+                // Current volume
+                let nii_slice = nii_image.get_slice(py, [z, t])?;
+
+                if let Some(pool) = &self.pool {
+                    let job = EncodeJob {
+                        slice: nii_slice.to_ubyte(self.img_as_ubyte)?,
+                        path: path.clone(),
+                        target_dir: png_stub.path.clone(),
+                        source: source.to_path_buf(),
+                    };
+                    py.allow_threads(|| pool.submit(job))?;
+                    tracing::debug!(path = %path.display(), "Submitted the slice");
+                    observer.slice_written(&path);
+                    if let Some(rows) = rows.as_deref_mut() {
+                        rows.push(manifest_row(&nii_image, source, [z, t], &path));
+                    }
+                    continue;
+                }
+
+                match &self.sink {
+                    Some(sink) => nii_slice
+                        .to_ubyte(self.img_as_ubyte)?
+                        .save(&**sink, &path)?,
+                    // skimage and PIL write the PNG twice, so the unrotated image
+                    // only ever exists under the temporary name
+                    None => TargetFile(path.clone()).write_atomically(|temp| {
+                        nii_slice.save(
+                            py_path(py, temp)?,
+                            self.io,
+                            self.color,
+                            self.img_as_ubyte,
+                            self.Image,
+                            self.ImageOps,
+                        )
+                    })?,
+                }
+                tracing::debug!(path = %path.display(), "Saved the slice");
+                observer.slice_written(&path);
+                if let Some(rows) = rows.as_deref_mut() {
+                    rows.push(manifest_row(&nii_image, source, [z, t], &path));
+                }
+                // let buffer = nii_slice.as_raw_rgb_image_buffer(py, io, color, img_as_ubyte, Image, ImageOps)?;
+                // println!("Buffer: {:?}", buffer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the slices of the volume as arrays instead of the PNGs, see [`ArrayExport`]
+    fn export_arrays<'a>(
+        &self,
+        py: Python<'a>,
+        png_stub: &ExistingImageDir,
+        source: &Path,
+        nii_image: &RescaledIntensityNiiImage<'a>,
+        observer: &mut dyn ConvertObserver,
+        mut rows: Option<&mut Vec<ManifestRow>>,
+    ) -> Result<(), ErrorTy>
+    where
+        'py: 'a,
+    {
+        let arrays = self.arrays.expect("only called with the arrays");
+        let sink: &dyn OutputSink = self.sink.as_deref().unwrap_or(&DirectorySink);
+        let dims = [nii_image.dim(0) as usize, nii_image.dim(1) as usize];
+        let [z_count, t_count] = [nii_image.dim(MAX_DIMS - 2), nii_image.dim(MAX_DIMS - 1)];
+        // The file that holds all arrays of the volume, if any
+        let volume_path = png_stub.path.join(match (arrays.layout, arrays.container) {
+            (ArrayLayout::Slices, _) => "slices.npz",
+            (ArrayLayout::Volume, ArrayContainer::Npy) => "volume.npy",
+            (ArrayLayout::Volume, ArrayContainer::Npz) => "volume.npz",
+        });
+        let mut npz = match arrays.container {
+            ArrayContainer::Npy => None,
+            ArrayContainer::Npz => Some(NpzWriter::new(volume_path.to_string_lossy().into_owned())),
+        };
+        let mut volume: Option<NpyData> = None;
+        // The slices in `volume_path` are reported once it is written
+        let mut unreported = Vec::new();
+
+        for t in 0..t_count {
+            observer.volume_started(t as usize, &png_stub.path);
+
+            for z in 0..z_count {
+                // SAFETY: only the copied voxels leave the iteration
+                let slice_gil_pool = unsafe { py.new_pool() };
+                let py = slice_gil_pool.python();
+                let _slice_span = tracing::debug_span!("slice", t, z).entered();
+
+                let nii_slice = nii_image.get_slice(py, [z, t])?;
+                let data = match arrays.dtype {
+                    ArrayDtype::Float32 => NpyData::Float32(
+                        orient(&nii_slice.to_f64()?, dims)
+                            .into_iter()
+                            .map(|v| v as f32)
+                            .collect(),
+                    ),
+                    ArrayDtype::Uint16 => NpyData::Uint16(
+                        orient(&nii_slice.to_f64()?, dims)
+                            .into_iter()
+                            .map(|v| {
+                                (v * u16::MAX as f64).round().clamp(0.0, u16::MAX as f64) as u16
+                            })
+                            .collect(),
+                    ),
+                    // img_as_ubyte, just like the PNGs
+                    ArrayDtype::Uint8 => {
+                        NpyData::Uint8(orient(&nii_slice.to_ubyte(self.img_as_ubyte)?.voxels, dims))
+                    }
+                };
+
+                let name = match t_count {
+                    1 => format!("{z:04}"),
+                    _ => format!("{t:04}_{z:04}"),
+                };
+                match (arrays.layout, &mut npz) {
+                    (ArrayLayout::Slices, None) => {
+                        let path = png_stub.path.join(format!("{name}.npy"));
+                        sink.write(&path, &npy::encode(&dims, &data))?;
+                        tracing::debug!(path = %path.display(), "Saved the slice");
+                        observer.slice_written(&path);
+                        if let Some(rows) = rows.as_deref_mut() {
+                            rows.push(manifest_row(nii_image, source, [z, t], &path));
+                        }
+                        continue;
+                    }
+                    (ArrayLayout::Slices, Some(npz)) => {
+                        npz.add(&name, &npy::encode(&dims, &data))?
+                    }
+                    (ArrayLayout::Volume, _) => match &mut volume {
+                        Some(volume) => volume.extend(data),
+                        None => volume = Some(data),
+                    },
+                }
+                unreported.push([z, t]);
+            }
+        }
+
+        if let Some(volume) = volume {
+            let mut shape = vec![z_count as usize, dims[0], dims[1]];
+            if t_count > 1 {
+                shape.insert(0, t_count as usize);
+            }
+            let npy = npy::encode(&shape, &volume);
+            match &mut npz {
+                Some(npz) => npz.add("volume", &npy)?,
+                None => sink.write(&volume_path, &npy)?,
+            }
+        }
+        if let Some(npz) = npz {
+            sink.write(&volume_path, &npz.finish()?)?;
+        }
+        if !unreported.is_empty() {
+            tracing::debug!(path = %volume_path.display(), "Saved the slices");
+        }
+        for index in unreported {
+            observer.slice_written(&volume_path);
+            if let Some(rows) = rows.as_deref_mut() {
+                rows.push(manifest_row(nii_image, source, index, &volume_path));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::ffi::{OsStr, OsString};
+
+use pyo3::prelude::*;
+
+use crate::paths::{os_from_bytes, os_to_bytes};
+
+/// Volume formats that nibabel can load and [`convert`](crate::convert) accepts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum VolumeFormat {
+    /// `.nii` and `.nii.gz` files, both NIfTI-1 and NIfTI-2
+    Nifti,
+    /// `.hdr/.img` pairs, both Analyze 7.5 and NIfTI-1/NIfTI-2 pairs
+    HdrImgPair,
+    /// `.mgh` and `.mgz` files
+    Mgh,
+    /// `.PAR/.REC` pairs
+    ParRec,
+}
+
+use VolumeFormat::*;
+
+/// Extension (as in [`PRIMARY_EXTENSIONS`]) and format of the file, compared case-insensitively
+fn primary_extension(file_name: &OsStr) -> Option<(&'static str, VolumeFormat)> {
+    let file_name = os_to_bytes(file_name);
+    PRIMARY_EXTENSIONS
+        .iter()
+        .find(|(ext, _)| {
+            file_name.len() > ext.len()
+                && file_name[file_name.len() - ext.len()..].eq_ignore_ascii_case(ext.as_bytes())
+        })
+        .copied()
+}
+
+/// Extensions of the files through which the volumes are loaded (lowercase).
+///
+/// The second file of a pair (`.img` or `.rec`) is loaded by nibabel through the first one
+/// and is deliberately absent here.
+const PRIMARY_EXTENSIONS: [(&str, VolumeFormat); 7] = [
+    (".nii", Nifti),
+    (".nii.gz", Nifti),
+    (".hdr", HdrImgPair),
+    (".hdr.gz", HdrImgPair),
+    (".mgh", Mgh),
+    (".mgz", Mgh),
+    (".par", ParRec),
+];
+
+impl VolumeFormat {
+    /// Detects the format of the volume by the name of the file through which it is loaded.
+    ///
+    /// Returns `None` for the second files of pairs and for files of unsupported formats.
+    pub(crate) fn detect(file_name: &OsStr) -> Option<Self> {
+        primary_extension(file_name).map(|(_, format)| format)
+    }
+
+    /// Name of the file without the extension of the format, e.g. `brain` for `brain.nii.gz`.
+    ///
+    /// Returns `None` when [`detect`](Self::detect) does.
+    pub(crate) fn stem(file_name: &OsStr) -> Option<OsString> {
+        let (ext, _) = primary_extension(file_name)?;
+        let bytes = os_to_bytes(file_name);
+        Some(os_from_bytes(bytes[..bytes.len() - ext.len()].to_vec()))
+    }
+
+    /// Extensions of the second file of a pair (lowercase), loaded along with the primary file
+    pub(crate) fn companion_extensions(self) -> &'static [&'static str] {
+        match self {
+            HdrImgPair => &[".img", ".img.gz"],
+            ParRec => &[".rec"],
+            Nifti | Mgh => &[],
+        }
+    }
+
+    pub(crate) fn load<'a>(self, nib: &'a PyModule, path: &PyAny) -> PyResult<&'a PyAny> {
+        match self {
+            // nibabel tells NIfTI-1 from NIfTI-2 (and Analyze from NIfTI pairs) by the header
+            Nifti | HdrImgPair | ParRec => nib.call_method1("load", (path,)),
+            Mgh => nib
+                .getattr("MGHImage")?
+                .call_method1("from_filename", (path,)),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Streaming access to the volumes of a directory, for exporters other than [`convert`](crate::convert).
+//!
+//! [`Volumes`] discovers the volumes the same way as [`convert`](crate::convert)
+//! (the supported formats, DICOM series, [`VolumeAccess`](crate::VolumeAccess) and
+//! [`MemoryBudget`](crate::MemoryBudget)) and loads them one at a time.
+//!
+//! The Python objects behind a [`Volume`] are owned by the GIL pool of the `py` token
+//! passed to [`Volumes::new`] and are only released when it is dropped. When iterating over
+//! many volumes, wrap every iteration in a pool of its own, like [`convert`](crate::convert) does
+//! (see [`Python::new_pool`]).
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use pyo3::prelude::*;
+
+use crate::{
+    error_ty::ErrorTy::{self, *},
+    nii_image::NiiImage,
+    output_names::OutputNames,
+    rel_nii_images_iter::RelNiiImagesIter,
+    ConvertOptions, MAX_DIMS,
+};
+
+/// The most useful fields of the header of a volume
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderSummary {
+    /// `get_data_shape()`, 3 or 4 dimensions
+    pub shape: Vec<usize>,
+    /// `get_data_dtype()`, e.g. `int16`
+    pub dtype: String,
+    /// `get_zooms()`, i.e. the voxel sizes (and the time step)
+    pub zooms: Vec<f64>,
+}
+
+impl HeaderSummary {
+    fn new(hdr: &PyAny) -> Result<Self, ErrorTy> {
+        Ok(Self {
+            shape: hdr.call_method0("get_data_shape")?.extract()?,
+            dtype: hdr.call_method0("get_data_dtype")?.str()?.extract()?,
+            zooms: hdr.call_method0("get_zooms")?.extract()?,
+        })
+    }
+}
+
+/// Loaded volume, see the [module-level documentation](self)
+pub struct Volume<'py> {
+    source: PathBuf,
+    target_dir: PathBuf,
+    header: HeaderSummary,
+    image: NiiImage<'py>,
+}
+
+impl<'py> Volume<'py> {
+    /// Loads the volume at the entry of the directory the same way as [`Volumes`].
+    /// The entry must be a volume file or a folder with a single DICOM series.
+    pub(crate) fn open(py: Python<'py>, dir: &Path, entry: &OsStr) -> Result<Self, ErrorTy> {
+        // The names of the output directories don't matter here, so the directory isn't listed
+        let options = ConvertOptions::builder(dir)
+            .entries([entry])
+            .output_names(OutputNames::default())
+            .build()?;
+        let source = || dir.join(entry).display().to_string();
+        let mut volumes = Volumes::new(py, &options)?;
+        let volume = volumes.next().ok_or_else(|| NoVolume(source()))??;
+        match volumes.images.pending_series() {
+            0 => Ok(volume),
+            pending => Err(MultipleDicomSeries(source(), pending + 1)),
+        }
+    }
+
+    /// Path to the file or, for DICOM series, to the folder with the series
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    /// Directory where [`convert`](crate::convert) would save the slices. It is not created.
+    pub fn target_dir(&self) -> &Path {
+        &self.target_dir
+    }
+
+    pub fn header(&self) -> &HeaderSummary {
+        &self.header
+    }
+
+    /// Number of slices along the third and the fourth dimensions, `[z, t]`.
+    /// 3D volumes have a single `t`.
+    pub fn slice_counts(&self) -> [usize; 2] {
+        [
+            self.image.dims[MAX_DIMS - 2] as usize,
+            self.image.dims[MAX_DIMS - 1] as usize,
+        ]
+    }
+
+    /// Returns the slice `[z, t]` as a 2D float64 ndarray, before any rescaling
+    pub fn slice(&self, py: Python<'py>, index: [usize; 2]) -> Result<&'py PyAny, ErrorTy> {
+        let counts = self.slice_counts();
+        if index[0] >= counts[0] || index[1] >= counts[1] {
+            return Err(SliceOutOfBounds(index, counts));
+        }
+        self.image
+            .get_slice(py, [index[0] as isize, index[1] as isize])
+    }
+
+    /// Same as [`slice`](Self::slice) but copied out of the Python heap.
+    ///
+    /// Returns the C-ordered voxels and the shape of the slice.
+    pub fn slice_values(
+        &self,
+        py: Python<'py>,
+        index: [usize; 2],
+    ) -> Result<(Vec<f64>, [usize; 2]), ErrorTy> {
+        let slice = self.slice(py, index)?;
+        let shape = slice.getattr("shape")?.extract::<(usize, usize)>()?;
+        let voxels = slice
+            .call_method0("ravel")?
+            .call_method0("tolist")?
+            .extract()?;
+        Ok((voxels, [shape.0, shape.1]))
+    }
+
+    pub(crate) fn into_image(self) -> NiiImage<'py> {
+        self.image
+    }
+}
+
+/// Iterator over the volumes of [`ConvertOptions::nii_files`], see the [module-level documentation](self).
+///
+/// Only the discovery-related options are used: `nii_files`, `entries`, `png_stub`,
+/// `volume_access` and `memory_budget`.
+pub struct Volumes<'py> {
+    images: RelNiiImagesIter<'py>,
+}
+
+impl<'py> Volumes<'py> {
+    pub fn new(py: Python<'py>, options: &ConvertOptions) -> Result<Self, ErrorTy> {
+        let os = py.import("os").map_err(MissingStandardLibrary)?;
+        let nib = py.import("nibabel").map_err(MissingThirdPartyLibrary)?;
+        Ok(Self {
+            images: RelNiiImagesIter::new(nib, os, options, None)?,
+        })
+    }
+}
+
+impl<'py> Iterator for Volumes<'py> {
+    type Item = Result<Volume<'py>, ErrorTy>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self.images.next()?;
+        Some(res.and_then(|(target_dir, source, image)| {
+            Ok(Volume {
+                header: HeaderSummary::new(image.hdr)?,
+                source,
+                target_dir: target_dir.path,
+                image,
+            })
+        }))
+    }
+}
@@ -0,0 +1,33 @@
+//! Logging setup shared by the executables of `nifti2png`
+
+use nifti2png::logging::{self, LogConfig, LogFormat, LOG_ENV_VAR, LOG_FORMAT_ENV_VAR};
+
+/// Installs the subscriber configured with the `--log <directives>` and `--log-format <format>`
+/// arguments or, without them, with [`LOG_ENV_VAR`] and [`LOG_FORMAT_ENV_VAR`].
+///
+/// The arguments are exported to the environment, so that the worker processes log the same way.
+pub(crate) fn init_logging() {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let var = match arg.as_str() {
+            "--log" => LOG_ENV_VAR,
+            "--log-format" => LOG_FORMAT_ENV_VAR,
+            _ => panic!("Unknown argument: {arg}"),
+        };
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("Missing the value of {arg}"));
+        std::env::set_var(var, value);
+    }
+
+    let mut config = LogConfig::default();
+    if let Ok(directives) = std::env::var(LOG_ENV_VAR) {
+        config.directives = directives;
+    }
+    config.format = match std::env::var(LOG_FORMAT_ENV_VAR).as_deref() {
+        Ok("text") | Err(_) => LogFormat::Text,
+        Ok("json") => LogFormat::Json,
+        Ok(format) => panic!("Unknown log format: {format}"),
+    };
+    logging::init(&config).unwrap();
+}
@@ -1,6 +1,41 @@
-use nifti2png::convert;
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use nifti2png::{
+    convert_with_observer, process_pool, ArchiveFormat, ArchiveSink, ArrayContainer, ArrayDtype,
+    ArrayExport, ArrayLayout, BidsFilter, ConvertOptions, ExportMode, FloatDtype, InputOrder,
+    ManifestFormat, MemoryBudget, NameCollision, OnError, OverBudget, OverwritePolicy,
+    VolumeAccess,
+};
+
+mod log_args;
+mod progress_bar;
+use progress_bar::ProgressBarObserver;
+
+/// Reads a line as raw bytes, since the paths don't have to be UTF-8
+fn read_path_line() -> PathBuf {
+    let mut line = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_until(b'\n', &mut line)
+        .unwrap();
+    while line.last().is_some_and(|byte| b"\r\n".contains(byte)) {
+        line.pop();
+    }
+    #[cfg(unix)]
+    let path = <std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(line);
+    #[cfg(not(unix))]
+    let path = String::from_utf8(line).expect("Invalid input");
+    PathBuf::from(path)
+}
 
 fn main() {
+    log_args::init_logging();
+    process_pool::run_worker_if_requested();
+
     println!(
         "Enter a path to a directory with NIFTI files, e.g. {example_asset}",
         example_asset = {
@@ -19,31 +54,231 @@ fn main() {
         }
         .display()
     );
-    let mut nii_files = String::new();
-    std::io::stdin().read_line(&mut nii_files).unwrap();
-    let nii_files = nii_files.trim_end();
+    let mut options = ConvertOptions::builder(read_path_line());
 
     println!("Enter the `png_stub`:");
-    let mut png_stub = String::new();
-    std::io::stdin().read_line(&mut png_stub).unwrap();
-    let png_stub = match png_stub.trim_end() {
-        "" => None,
-        png_stub => Some(png_stub),
+    let png_stub = read_path_line();
+    if !png_stub.as_os_str().is_empty() {
+        options = options.png_stub(png_stub);
+    }
+
+    println!("Enter `mtime` to convert the files from the least to the most recently modified (empty for the natural order of names):");
+    let mut input_order = String::new();
+    std::io::stdin().read_line(&mut input_order).unwrap();
+    options = options.input_order(match input_order.trim_end() {
+        "" => InputOrder::Natural,
+        "mtime" => InputOrder::ModifiedTime,
+        _ => panic!("Invalid input"),
+    });
+
+    println!("Enter `bids` followed by filters such as `sub=01,02 ses=1 task=rest run=1 suffix=T1w` to convert a BIDS dataset (empty for a plain directory):");
+    let mut bids = String::new();
+    std::io::stdin().read_line(&mut bids).unwrap();
+    match bids.split_whitespace().collect::<Vec<_>>()[..] {
+        [] => (),
+        ["bids", ref filters @ ..] => {
+            let mut filter = BidsFilter::default();
+            for item in filters {
+                let (key, labels) = item.split_once('=').expect("Invalid input");
+                let labels = labels.split(',').map(str::to_string);
+                match key {
+                    "sub" => filter.subjects.extend(labels),
+                    "ses" => filter.sessions.extend(labels),
+                    "task" => filter.tasks.extend(labels),
+                    "run" => filter.runs.extend(labels),
+                    "suffix" => filter.suffixes.extend(labels),
+                    _ => panic!("Invalid input"),
+                }
+            }
+            options = options.bids(filter);
+        }
+        _ => panic!("Invalid input"),
     };
 
     println!("Enter the `minmax`:");
     let mut minmax = String::new();
     std::io::stdin().read_line(&mut minmax).unwrap();
-    let min_max = match minmax
-        .trim_end()
+    match minmax
         .split_whitespace()
         .map(|s| s.parse::<u64>())
         .collect::<Vec<_>>()[..]
     {
-        [] => None,
-        [Ok(min), Ok(max)] => Some((min, max)),
+        [] => (),
+        [Ok(min), Ok(max)] => options = options.minmax(min, max),
+        _ => panic!("Invalid input"),
+    };
+
+    println!("Enter `npy` or `npz` followed by the dtype (float32, uint16 or uint8) and optionally `volume` to save NumPy arrays instead of PNGs, e.g. `npz float32 volume` (empty for PNGs):");
+    let mut arrays = String::new();
+    std::io::stdin().read_line(&mut arrays).unwrap();
+    match arrays.split_whitespace().collect::<Vec<_>>()[..] {
+        [] => (),
+        [container, dtype, ref layout @ ..] => {
+            options = options.array_export(ArrayExport {
+                dtype: match dtype {
+                    "float32" => ArrayDtype::Float32,
+                    "uint16" => ArrayDtype::Uint16,
+                    "uint8" => ArrayDtype::Uint8,
+                    _ => panic!("Invalid input"),
+                },
+                layout: match layout {
+                    [] => ArrayLayout::Slices,
+                    ["volume"] => ArrayLayout::Volume,
+                    _ => panic!("Invalid input"),
+                },
+                container: match container {
+                    "npy" => ArrayContainer::Npy,
+                    "npz" => ArrayContainer::Npz,
+                    _ => panic!("Invalid input"),
+                },
+            })
+        }
+        _ => panic!("Invalid input"),
+    };
+
+    println!("Enter the number of threads for encoding slices (empty for sequential export):");
+    let mut threads = String::new();
+    std::io::stdin().read_line(&mut threads).unwrap();
+    options = options.export_mode(match threads.trim_end() {
+        "" => ExportMode::Sequential,
+        threads => ExportMode::Parallel(threads.parse().expect("Invalid input")),
+    });
+
+    println!("Enter `float32` or `float64` to decode whole volumes or `lazy` for slice access (empty for float64 within the memory budget below):");
+    let mut volume_access = String::new();
+    std::io::stdin().read_line(&mut volume_access).unwrap();
+    options = options.volume_access(match volume_access.trim_end() {
+        "" => VolumeAccess::Auto,
+        "lazy" => VolumeAccess::Lazy,
+        "float32" => VolumeAccess::Full(FloatDtype::Float32),
+        "float64" => VolumeAccess::Full(FloatDtype::Float64),
+        _ => panic!("Invalid input"),
+    });
+
+    println!("Enter the memory budget per volume in MiB followed by `reject` or `stream` (empty for no budget):");
+    let mut memory_budget = String::new();
+    std::io::stdin().read_line(&mut memory_budget).unwrap();
+    match memory_budget.split_whitespace().collect::<Vec<_>>()[..] {
+        [] => (),
+        [mib, on_exceed] => {
+            options = options.memory_budget(MemoryBudget {
+                bytes: mib
+                    .parse::<u64>()
+                    .expect("Invalid input")
+                    .checked_mul(1 << 20)
+                    .expect("The memory budget doesn't fit into 64 bits"),
+                on_exceed: match on_exceed {
+                    "reject" => OverBudget::Reject,
+                    "stream" => OverBudget::Stream,
+                    _ => panic!("Invalid input"),
+                },
+            })
+        }
+        _ => panic!("Invalid input"),
+    };
+
+    println!("Enter `continue` to skip the files that fail to convert (empty to stop at the first error):");
+    let mut on_error = String::new();
+    std::io::stdin().read_line(&mut on_error).unwrap();
+    options = options.on_error(match on_error.trim_end() {
+        "" => OnError::Stop,
+        "continue" => OnError::Continue,
+        _ => panic!("Invalid input"),
+    });
+
+    println!("Enter `resume` to skip the files converted by a previous run with the same settings (empty to convert everything):");
+    let mut resume = String::new();
+    std::io::stdin().read_line(&mut resume).unwrap();
+    let resume = match resume.trim_end() {
+        "" => false,
+        "resume" => true,
         _ => panic!("Invalid input"),
     };
+    options = options.resume(resume);
 
-    convert(nii_files, png_stub, min_max).unwrap();
+    // The exports that weren't completed are overwritten by a resumed conversion
+    if resume {
+        options = options.overwrite(OverwritePolicy::Overwrite);
+    } else {
+        println!("Enter `skip`, `overwrite` or `versioned` to handle existing exports (empty to fail on them):");
+        let mut overwrite = String::new();
+        std::io::stdin().read_line(&mut overwrite).unwrap();
+        options = options.overwrite(match overwrite.trim_end() {
+            "" => OverwritePolicy::Error,
+            "skip" => OverwritePolicy::SkipExisting,
+            "overwrite" => OverwritePolicy::Overwrite,
+            "versioned" => OverwritePolicy::Versioned,
+            _ => panic!("Invalid input"),
+        });
+    }
+
+    println!("Enter `suffix` to add `-2`, `-3`, ... to the names shared by several files, e.g. `a.nii` and `a.nii.gz` (empty to fail on them):");
+    let mut name_collision = String::new();
+    std::io::stdin().read_line(&mut name_collision).unwrap();
+    options = options.name_collision(match name_collision.trim_end() {
+        "" => NameCollision::Error,
+        "suffix" => NameCollision::Suffix,
+        _ => panic!("Invalid input"),
+    });
+
+    println!(
+        "Enter `csv` or `json` to write a manifest of the exported images (empty for no manifest):"
+    );
+    let mut output_manifest = String::new();
+    std::io::stdin().read_line(&mut output_manifest).unwrap();
+    match output_manifest.trim_end() {
+        "" => (),
+        "csv" => options = options.output_manifest(ManifestFormat::Csv),
+        "json" => options = options.output_manifest(ManifestFormat::Json),
+        _ => panic!("Invalid input"),
+    };
+
+    println!("Enter a `.zip` or `.tar` path to write all files to a single archive (empty for directories):");
+    let mut archive = String::new();
+    std::io::stdin().read_line(&mut archive).unwrap();
+    let archive = archive.trim_end();
+    let format = match archive.rsplit_once('.') {
+        _ if archive.is_empty() => None,
+        Some((_, "zip")) => Some(ArchiveFormat::Zip),
+        Some((_, "tar")) => Some(ArchiveFormat::Tar),
+        _ => panic!("Invalid input"),
+    };
+    if let Some(format) = format {
+        options = options.output_sink(Arc::new(ArchiveSink::create(archive, format).unwrap()));
+    }
+
+    let options = options.build().unwrap();
+
+    println!("Enter the number of worker processes (empty for a single process):");
+    let mut processes = String::new();
+    std::io::stdin().read_line(&mut processes).unwrap();
+    let failed = match processes.trim_end() {
+        "" => {
+            let report = convert_with_observer(&options, &mut ProgressBarObserver::new()).unwrap();
+            println!("{report}");
+            report.failed.len()
+        }
+        processes => {
+            let processes = processes.parse().expect("Invalid input");
+            let report = process_pool::convert_in_processes_with_observer(
+                &options,
+                processes,
+                &mut ProgressBarObserver::new(),
+            )
+            .unwrap();
+            println!(
+                "Converted: {}, skipped: {}, failed: {}",
+                report.converted.len(),
+                report.skipped.len(),
+                report.failed.len()
+            );
+            for (entry, e) in report.failed.iter() {
+                println!("\t{}: {e}", Path::new(entry).display());
+            }
+            report.failed.len()
+        }
+    };
+    if failed > 0 {
+        std::process::exit(1);
+    }
 }
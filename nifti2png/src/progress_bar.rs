@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use nifti2png::{ConvertObserver, ConvertReport, ErrorTy};
+
+/// Renders the entries of the whole run as a progress bar with ETA
+/// and the slices of the current file as its message
+pub(crate) struct ProgressBarObserver {
+    bar: ProgressBar,
+    shown: bool,
+    // the current file and its number of slices
+    source: String,
+    slices: usize,
+    written: usize,
+}
+
+impl ProgressBarObserver {
+    pub(crate) fn new() -> Self {
+        // Shown once the entries are listed
+        let bar = ProgressBar::hidden();
+        bar.set_style(
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] {wide_bar} {pos}/{len} files (ETA {eta}) {msg}",
+            )
+            .expect("the template is valid"),
+        );
+        Self {
+            bar,
+            shown: false,
+            source: String::new(),
+            slices: 0,
+            written: 0,
+        }
+    }
+
+    fn show_slices(&self) {
+        self.bar.set_message(format!(
+            "{}: {}/{} slices",
+            self.source, self.written, self.slices
+        ));
+    }
+}
+
+impl ConvertObserver for ProgressBarObserver {
+    fn entries_progress(&mut self, done: usize, total: usize) {
+        if !self.shown {
+            self.shown = true;
+            self.bar
+                .set_draw_target(indicatif::ProgressDrawTarget::stderr());
+            self.bar.reset();
+        }
+        self.bar.set_length(total as u64);
+        self.bar.set_position(done as u64);
+    }
+
+    fn file_started(&mut self, source: &Path, _target_dir: &Path, dims: [usize; 4]) {
+        self.source = source.display().to_string();
+        self.slices = dims[2] * dims[3];
+        self.written = 0;
+        self.show_slices();
+    }
+
+    fn slice_written(&mut self, _path: &Path) {
+        self.written += 1;
+        self.show_slices();
+    }
+
+    fn file_finished(&mut self, source: &Path) {
+        self.bar
+            .println(format!("{}: {} slices", source.display(), self.written));
+    }
+
+    fn file_failed(&mut self, source: &Path, error: &ErrorTy) {
+        self.bar
+            .println(format!("{}: failed: {error}", source.display()));
+    }
+
+    fn error(&mut self, error: &ErrorTy) {
+        self.bar.abandon_with_message(error.to_string());
+    }
+
+    fn finished(&mut self, _report: &ConvertReport) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,73 @@
+//! Archives with rewritten paths
+
+use std::{io::Read, path::Path};
+
+use nifti2png::{ArchiveFormat, ArchiveSink, OutputSink};
+
+#[test]
+fn rewritten_path_keeps_last_content() {
+    let dir = std::env::temp_dir().join(format!("nifti2png-archive-sink-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("slices.tar");
+
+    let sink = ArchiveSink::create(&path, ArchiveFormat::Tar).unwrap();
+    sink.write(Path::new("brain/0000.png"), b"first").unwrap();
+    sink.write(Path::new("brain/0001.png"), b"other").unwrap();
+    sink.write(Path::new("brain/0000.png"), b"last").unwrap();
+    sink.finish().unwrap();
+
+    let mut archive = tar::Archive::new(std::fs::File::open(&path).unwrap());
+    let entries: Vec<_> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            (entry.path().unwrap().display().to_string(), content)
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("brain/0000.png".to_string(), "last".to_string()),
+            ("brain/0001.png".to_string(), "other".to_string()),
+        ]
+    );
+    // Only the archive is left behind
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn zip_keeps_last_content() {
+    let dir =
+        std::env::temp_dir().join(format!("nifti2png-archive-sink-zip-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("slices.zip");
+
+    let sink = ArchiveSink::create(&path, ArchiveFormat::Zip).unwrap();
+    sink.write(Path::new("brain/0001.png"), b"other").unwrap();
+    sink.write(Path::new("brain/0000.png"), b"first").unwrap();
+    sink.write(Path::new("brain/0000.png"), b"last").unwrap();
+    sink.finish().unwrap();
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).unwrap();
+        assert_eq!(entry.compression(), zip::CompressionMethod::Deflated);
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        entries.push((entry.name().to_string(), content));
+    }
+    assert_eq!(
+        entries,
+        [
+            ("brain/0000.png".to_string(), "last".to_string()),
+            ("brain/0001.png".to_string(), "other".to_string()),
+        ]
+    );
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,70 @@
+//! Both export modes write the same images
+
+use std::{num::NonZeroUsize, path::Path};
+
+use nifti2png::{convert_with_observer, ConvertOptions, ExportMode, SilentObserver};
+
+const DIMS: [usize; 4] = [5, 3, 2, 3];
+
+/// Uncompressed NIfTI-1 volume of uint8 voxels that differ in every 3D volume
+fn write_nifti(path: &Path) {
+    let mut header = vec![0u8; 352];
+    header[0..4].copy_from_slice(&348i32.to_le_bytes());
+    for (i, dim) in [4, DIMS[0], DIMS[1], DIMS[2], DIMS[3], 1, 1, 1]
+        .iter()
+        .enumerate()
+    {
+        header[40 + 2 * i..42 + 2 * i].copy_from_slice(&(*dim as i16).to_le_bytes());
+    }
+    // datatype uint8, bitpix
+    header[70..72].copy_from_slice(&2i16.to_le_bytes());
+    header[72..74].copy_from_slice(&8i16.to_le_bytes());
+    for i in 0..8 {
+        header[76 + 4 * i..80 + 4 * i].copy_from_slice(&1f32.to_le_bytes());
+    }
+    // vox_offset
+    header[108..112].copy_from_slice(&352f32.to_le_bytes());
+    header[344..348].copy_from_slice(b"n+1\0");
+    let voxels = (0..DIMS.iter().product::<usize>()).map(|i| {
+        let t = i / (DIMS[0] * DIMS[1] * DIMS[2]);
+        ((i * 7 + t * 50) % 256) as u8
+    });
+    header.extend(voxels);
+    std::fs::write(path, header).unwrap();
+}
+
+fn export(nii_files: &Path, png_stub: &Path, export_mode: ExportMode) {
+    let options = ConvertOptions::builder(nii_files)
+        .png_stub(png_stub)
+        .export_mode(export_mode)
+        .build()
+        .unwrap();
+    convert_with_observer(&options, &mut SilentObserver).unwrap();
+}
+
+#[test]
+#[ignore = "needs nibabel, scikit-image and Pillow"]
+fn parallel_4d_matches_sequential() {
+    let dir = std::env::temp_dir().join(format!("nifti2png-export-mode-{}", std::process::id()));
+    let nii_files = dir.join("nii");
+    std::fs::create_dir_all(&nii_files).unwrap();
+    write_nifti(&nii_files.join("volume.nii"));
+
+    export(&nii_files, &dir.join("sequential"), ExportMode::Sequential);
+    for _ in 0..5 {
+        let parallel = dir.join("parallel");
+        let _ = std::fs::remove_dir_all(&parallel);
+        export(
+            &nii_files,
+            &parallel,
+            ExportMode::Parallel(NonZeroUsize::new(4).unwrap()),
+        );
+        for z in 0..DIMS[2] {
+            let name = format!("volume/{z:04}.png");
+            let sequential = image::open(dir.join("sequential").join(&name)).unwrap();
+            let parallel = image::open(parallel.join(&name)).unwrap();
+            assert_eq!(sequential.to_rgb8(), parallel.to_rgb8(), "{name}");
+        }
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,109 @@
+//! Uploads to a local stand-in of S3 that records the request
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    path::Path,
+    thread,
+};
+
+use hmac::{Hmac, Mac};
+use nifti2png::{OutputSink, S3Sink};
+use sha2::{Digest, Sha256};
+
+struct Request {
+    method: String,
+    path: String,
+    // Lowercase names
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Accepts a single request and answers it with `200 OK`
+fn serve_once(listener: TcpListener) -> thread::JoinHandle<Request> {
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut request_line = line.split_whitespace();
+        let method = request_line.next().unwrap().to_string();
+        let path = request_line.next().unwrap().to_string();
+        let mut headers = HashMap::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+        }
+        let mut body = vec![0; headers["content-length"].parse().unwrap()];
+        reader.read_exact(&mut body).unwrap();
+        (&stream)
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        Request {
+            method,
+            path,
+            headers,
+            body,
+        }
+    })
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[test]
+fn put_is_signed_with_sigv4() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+    let server = serve_once(listener);
+
+    let sink = S3Sink::new(
+        format!("http://{host}/"),
+        "bucket",
+        "eu-central-1",
+        "AKID",
+        "SECRET",
+    );
+    let bytes = b"\x89PNG fake";
+    sink.write(Path::new("slice/brain scan/0000.png"), bytes)
+        .unwrap();
+    let request = server.join().unwrap();
+
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.path, "/bucket/slice/brain%20scan/0000.png");
+    assert_eq!(request.headers["host"], host);
+    assert_eq!(request.body, bytes);
+    let payload_hash = hex::encode(Sha256::digest(bytes));
+    assert_eq!(request.headers["x-amz-content-sha256"], payload_hash);
+
+    let amz_date = &request.headers["x-amz-date"];
+    let date = &amz_date[..8];
+    let scope = format!("{date}/eu-central-1/s3/aws4_request");
+    let canonical_request = format!(
+        "PUT\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}",
+        request.path
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signing_key = hmac(
+        &hmac(&hmac(&hmac(b"AWS4SECRET", date), "eu-central-1"), "s3"),
+        "aws4_request",
+    );
+    let signature = hex::encode(hmac(&signing_key, &string_to_sign));
+    assert_eq!(
+        request.headers["authorization"],
+        format!(
+            "AWS4-HMAC-SHA256 Credential=AKID/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}"
+        )
+    );
+}
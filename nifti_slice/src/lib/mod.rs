@@ -24,8 +24,6 @@ pub enum ErrorTy {
     MissingThirdPartyLibrary(PyErr),
     #[error("Missing a component of a third-party Python library: {0}")]
     MissingComponentOfThirdPartyLibrary(PyErr),
-    #[error("A path to the temporary directory is not UTF-8.")]
-    TempDirIsNotUtf8,
     #[error("Uncategorised Python error: {0}")]
     UncategorisedPyError(#[from] PyErr),
 }
@@ -1,22 +1,30 @@
+use std::path::Path;
+
 use arrayvec::ArrayVec;
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PySlice, PyUnicode};
+use pyo3::types::{PyDict, PySlice};
 
 use crate::{ErrorTy, PythonDeps, RescaledIntensityNiftiImage, MAX_DIMS, SECONDARY_DIMS, PRIMARY_DIMS};
 
 pub(crate) struct NiftiImage<'a> {
-    pub(crate) fdata: &'a PyAny,
+    // nibabel's array proxy, which decodes only the requested slices
+    pub(crate) dataobj: &'a PyAny,
     pub(crate) dims: ArrayVec<isize, MAX_DIMS>,
 }
 
 impl<'a> NiftiImage<'a> {
-    pub(crate) fn open(py_deps: &PythonDeps<'a>, path: &str) -> Result<Self, ErrorTy> {
-        let py_path = PyUnicode::new(py_deps.py, path);
+    pub(crate) fn open(py_deps: &PythonDeps<'a>, path: &Path) -> Result<Self, ErrorTy> {
+        // pathlib.Path, so that the paths which aren't UTF-8 work too
+        let py_path = py_deps
+            .py
+            .import("pathlib")?
+            .getattr("Path")?
+            .call1((path,))?;
         let nii_obj = py_deps
             .nib
             .call_method1("load", (py_path,))
-            .map_err(|e| ErrorTy::FailedToLoadNiftiObj(e, path.to_string()))?;
-        let fdata = nii_obj.call_method0("get_fdata")?;
+            .map_err(|e| ErrorTy::FailedToLoadNiftiObj(e, path.display().to_string()))?;
+        let dataobj = nii_obj.getattr("dataobj")?;
         let hdr = nii_obj.getattr("header")?;
         let nii_shape = hdr.call_method0("get_data_shape")?;
         let mut dims = ArrayVec::new();
@@ -26,10 +34,16 @@ impl<'a> NiftiImage<'a> {
         dims.push(match nii_shape.len()? {
             3 => 1,
             4 => nii_shape.get_item(3)?.extract::<isize>()?,
-            len => return Err(ErrorTy::UnsupportedDimensionality(len, path.to_string())),
+            len => {
+                return Err(ErrorTy::UnsupportedDimensionality(
+                    len,
+                    path.display().to_string(),
+                ))
+            }
         });
+        tracing::debug!(?dims, "Loaded the header");
 
-        Ok(NiftiImage { fdata, dims })
+        Ok(NiftiImage { dataobj, dims })
     }
 
     pub(crate) fn primary_dims(&self) -> [isize; PRIMARY_DIMS] {
@@ -40,29 +54,55 @@ impl<'a> NiftiImage<'a> {
         std::array::from_fn(|i| self.dims[i + PRIMARY_DIMS])
     }
 
+    /// Minimum and maximum intensity of the whole volume, found slice by slice
+    fn intensity_range(&self, py_deps: &PythonDeps<'a>) -> Result<(f64, f64), ErrorTy> {
+        let [s, t] = self.secondary_dims();
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for idx in (0..s).flat_map(|z| (0..t).map(move |t| [z, t])) {
+            let slice = self.slice(py_deps, idx)?;
+            min = min.min(slice.call_method0("min")?.extract::<f64>()?);
+            max = max.max(slice.call_method0("max")?.extract::<f64>()?);
+        }
+        Ok((min, max))
+    }
+
     pub(crate) fn rescale_intensity_to_unit_interval(
-        mut self,
+        self,
         py_deps: &PythonDeps<'a>,
         minmax: Option<(u64, u64)>,
     ) -> Result<RescaledIntensityNiftiImage<'a>, ErrorTy> {
-        // Rescale to 0..255 uint8
-        self.fdata = py_deps.exposure.call_method(
+        // Rescaling every slice with the range of the whole volume
+        // is the same as rescaling the whole volume at once.
+        let in_range = match minmax {
+            // Clamp input range if requested
+            Some((imin, imax)) => (imin as f64, imax as f64),
+            None => self.intensity_range(py_deps)?,
+        };
+        Ok(RescaledIntensityNiftiImage {
+            image: self,
+            in_range,
+        })
+    }
+
+    pub(crate) fn rescaled_slice(
+        &self,
+        py_deps: &PythonDeps<'a>,
+        index: [isize; SECONDARY_DIMS],
+        in_range: (f64, f64),
+    ) -> Result<&'a PyAny, ErrorTy> {
+        let slice = self.slice(py_deps, index)?;
+        // Rescale to 0..1 float64
+        let slice = py_deps.exposure.call_method(
             "rescale_intensity",
-            (self.fdata,),
+            (slice,),
             Some({
                 let dict = PyDict::new(py_deps.py);
-                // Clamp input range if requested
-                match minmax {
-                    Some((imin, imax)) => {
-                        dict.set_item("in_range", (imin, imax))?;
-                    }
-                    None => (),
-                }
+                dict.set_item("in_range", in_range)?;
                 dict.set_item("out_range", (0.0, 1.0))?;
                 dict
             }),
         )?;
-        Ok(RescaledIntensityNiftiImage(self))
+        Ok(slice)
     }
 
     pub(crate) fn slice(
@@ -72,7 +112,7 @@ impl<'a> NiftiImage<'a> {
     ) -> Result<&'a PyAny, ErrorTy> {
         debug_assert!(index.len() == self.dims.len() - 2);
         let slice = if self.dims[MAX_DIMS - 1] > 1 {
-            self.fdata.get_item((
+            self.dataobj.get_item((
                 // Slicing using : is not supported in PyO3 yet
                 // https://github.com/PyO3/pyo3/issues/3000
                 PySlice::new(py_deps.py, 0, self.dims[0], 1),
@@ -81,12 +121,13 @@ impl<'a> NiftiImage<'a> {
                 index[1],
             ))?
         } else {
-            self.fdata.get_item((
+            self.dataobj.get_item((
                 PySlice::new(py_deps.py, 0, self.dims[0], 1),
                 PySlice::new(py_deps.py, 0, self.dims[1], 1),
                 index[0],
             ))?
         };
-        Ok(slice)
+        // The array proxy returns the slice in the on-disk dtype (scaled by scl_slope and scl_inter)
+        Ok(slice.call_method1("astype", ("float64",))?)
     }
 }
@@ -1,9 +1,9 @@
 use pyo3::prelude::*;
-use pyo3::types::PyUnicode;
 use crate::ErrorTy::{*, self};
 
 pub(crate) struct Tempfile<'a> {
-    pub(crate) py_str: &'a pyo3::types::PyString,
+    // pathlib.Path, so that the paths which aren't UTF-8 work too
+    pub(crate) py_path: &'a PyAny,
     pub(crate) rust_path_buf: std::path::PathBuf,
 }
 
@@ -59,7 +59,7 @@ impl<'a> PythonDeps<'a> {
             .map_err(MissingThirdPartyLibrary)?;
 
         let tempfile = std::env::temp_dir().join("tempfile.png");
-        let py_str = tempfile.to_str().ok_or(TempDirIsNotUtf8)?;
+        let py_path = py.import("pathlib")?.getattr("Path")?.call1((&tempfile,))?;
 
         Ok(PythonDeps {
             py,
@@ -71,7 +71,7 @@ impl<'a> PythonDeps<'a> {
             Image,
             ImageOps,
             tempfile: Tempfile {
-                py_str: PyUnicode::new(py, py_str),
+                py_path,
                 rust_path_buf: tempfile,
             },
         })
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use image::RgbaImage;
 use pyo3::PyAny;
 
@@ -30,20 +32,29 @@ fn save_ubyte_rgb_grayscale_slice<'a>(
     Ok(())
 }
 
-pub struct RescaledIntensityNiftiImage<'a>(pub(crate) NiftiImage<'a>);
+pub struct RescaledIntensityNiftiImage<'a> {
+    pub(crate) image: NiftiImage<'a>,
+    // the range of intensities mapped to [0, 1]
+    pub(crate) in_range: (f64, f64),
+}
 
 impl<'a> RescaledIntensityNiftiImage<'a> {
-    pub fn new(py_deps: &PythonDeps<'a>, path: &str, minmax: Option<(u64, u64)>) -> Result<Self, ErrorTy> {
-        let nii = NiftiImage::open(&py_deps, path)?;
-        nii.rescale_intensity_to_unit_interval(&py_deps, minmax)
+    #[tracing::instrument(name = "file", skip(py_deps, path), fields(path = %path.as_ref().display()))]
+    pub fn new(
+        py_deps: &PythonDeps<'a>,
+        path: impl AsRef<Path>,
+        minmax: Option<(u64, u64)>,
+    ) -> Result<Self, ErrorTy> {
+        let nii = NiftiImage::open(py_deps, path.as_ref())?;
+        nii.rescale_intensity_to_unit_interval(py_deps, minmax)
     }
 
     pub fn primary_dims(&self) -> [isize; SECONDARY_DIMS] {
-        self.0.primary_dims()
+        self.image.primary_dims()
     }
 
     pub fn secondary_dims(&self) -> [isize; SECONDARY_DIMS] {
-        self.0.secondary_dims()
+        self.image.secondary_dims()
     }
 
     fn slice(
@@ -51,7 +62,7 @@ impl<'a> RescaledIntensityNiftiImage<'a> {
         py_deps: &PythonDeps<'a>,
         index: [isize; SECONDARY_DIMS],
     ) -> Result<&'a PyAny, ErrorTy> {
-        self.0.slice(py_deps, index)
+        self.image.rescaled_slice(py_deps, index, self.in_range)
     }
 
     fn save_slice(
@@ -71,12 +82,13 @@ impl<'a> RescaledIntensityNiftiImage<'a> {
         idx: [isize; SECONDARY_DIMS],
     ) -> Result<RgbaImage, ErrorTy> {
         let path = &py_deps.tempfile.rust_path_buf;
-        self.save_slice(py_deps, py_deps.tempfile.py_str, idx)?;
+        self.save_slice(py_deps, py_deps.tempfile.py_path, idx)?;
         let img = image::open(path)
             .map_err(|e| ErrorTy::ImageOpenFailed(e, path.to_string_lossy().to_string()))?;
         Ok(img.to_rgba8())
     }
 
+    #[tracing::instrument(name = "slice", skip(self, py_deps))]
     pub fn slice_as_raw_rgba(
         &self,
         py_deps: &PythonDeps<'a>,
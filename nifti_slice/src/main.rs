@@ -1,7 +1,45 @@
 use nifti_slice::{PythonDeps, RescaledIntensityNiftiImage};
 use pyo3::prelude::*;
+use tracing_subscriber::EnvFilter;
+
+/// Same as `NIFTI2PNG_LOG` of `nifti2png`, e.g. `debug`. `info` by default.
+const LOG_ENV_VAR: &str = "NIFTI_SLICE_LOG";
+/// `text` (by default) or `json`
+const LOG_FORMAT_ENV_VAR: &str = "NIFTI_SLICE_LOG_FORMAT";
+
+/// Configures the subscriber with the `--log <directives>` and `--log-format <format>` arguments
+/// or, without them, with [`LOG_ENV_VAR`] and [`LOG_FORMAT_ENV_VAR`]
+fn init_logging() {
+    let (mut directives, mut format) = (
+        std::env::var(LOG_ENV_VAR).ok(),
+        std::env::var(LOG_FORMAT_ENV_VAR).ok(),
+    );
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("Missing the value of {arg}"));
+        match arg.as_str() {
+            "--log" => directives = Some(value),
+            "--log-format" => format = Some(value),
+            _ => panic!("Unknown argument: {arg}"),
+        }
+    }
+
+    let filter = EnvFilter::try_new(directives.as_deref().unwrap_or("info")).unwrap();
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format.as_deref() {
+        Some("json") => builder.json().init(),
+        Some("text") | None => builder.init(),
+        Some(format) => panic!("Unknown log format: {format}"),
+    }
+}
 
 pub fn main() {
+    init_logging();
+
     Python::with_gil(|py| {
         println!(
             "Enter a path to a NIFTI file, e.g. {example_asset}",
@@ -31,7 +69,6 @@ pub fn main() {
         let mut minmax = String::new();
         std::io::stdin().read_line(&mut minmax).unwrap();
         let min_max = match minmax
-            .trim_end()
             .split_whitespace()
             .map(|s| s.parse::<u64>())
             .collect::<Vec<_>>()[..]
@@ -60,7 +97,8 @@ pub fn main() {
                         _ => panic!("Invalid input"),
                     };
                     let png = nifti.slice_as_raw_rgba(&py_deps, idx).unwrap();
-                    dbg!(png);
+                    tracing::info!(index = ?idx, bytes = png.len(), "Extracted the RGBA slice");
+                    tracing::debug!(?png);
                 }
             }
         };